@@ -0,0 +1,37 @@
+use crate::lexer::Location;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CompileErrorKind {
+    /// a variable, map key or detached ruleset which is not defined, or defined by itself,
+    /// kept as it is in the output
+    UndefinedVariable,
+    /// a mixin call without any mixin taking its arguments, eg. ".m(1; 2)" of ".m(@a)"
+    UnmatchedMixin,
+    /// a mixin call which is dropped, as it calls itself with the same arguments
+    RecursiveMixin,
+    /// a mixin call which is dropped, as mixin calls nest or expand too much
+    TooManyExpansions,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub message: String,
+    pub span: Location,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}",
+            self.message,
+            self.span.start.line + 1,
+            self.span.start.column + 1
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
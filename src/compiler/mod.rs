@@ -0,0 +1,1420 @@
+mod error;
+
+use super::lexer::{Lexer, Location, Token};
+use super::parser::{
+    parse_component_values, parse_prelude, parse_selector_list, search_when, GuardEvaluator,
+    NodeType, Raws,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+pub use error::{CompileError, CompileErrorKind};
+
+/// mixin calls in blocks nested deeper are dropped and reported, blocks of expanded mixins
+/// counted too, eg. a loop of more than 150 iterations, as each one takes two
+const MAX_DEPTH: usize = 300;
+
+/// mixin calls after this many expansions are dropped, eg. of a mixin calling itself twice
+const MAX_EXPANSIONS: usize = 10000;
+
+/// at-rules whose blocks hold declarations or keyframes, not rules of the enclosing selector
+const DESCRIPTOR_ATRULES: [&str; 7] = [
+    "font-face",
+    "page",
+    "counter-style",
+    "property",
+    "font-feature-values",
+    "font-palette-values",
+    "viewport",
+];
+
+/// evaluated arguments of a mixin call, with the names of named ones
+type Arguments = Vec<(Option<String>, String)>;
+
+/// value of a less variable in a scope
+#[derive(Clone)]
+enum Binding<'n> {
+    /// source text evaluated on use, eg. of "@a: @b + 1"
+    Lazy(String),
+    /// evaluated text, eg. of a mixin argument
+    Value(String),
+    /// detached ruleset or map
+    Ruleset(&'n [NodeType]),
+}
+
+/// variables and mixins of a block
+#[derive(Default)]
+struct Frame<'n> {
+    variables: HashMap<String, Binding<'n>>,
+    /// mixin definitions and rules which can be called as mixins, eg. ".a" or "#ns"
+    mixins: Vec<&'n NodeType>,
+}
+
+impl<'n> Frame<'n> {
+    fn new(nodes: &'n [NodeType]) -> Frame<'n> {
+        let mut frame = Frame::default();
+        for node in nodes {
+            match node {
+                // the last definition in the scope wins, even after its usage
+                NodeType::VariableDecl {
+                    name,
+                    value,
+                    important,
+                    nodes,
+                    ..
+                } => {
+                    let binding = match nodes {
+                        Some(nodes) => Binding::Ruleset(nodes),
+                        None if important.unwrap_or(false) => {
+                            Binding::Lazy(format!("{} !important", value))
+                        }
+                        None => Binding::Lazy(value.to_owned()),
+                    };
+                    frame.variables.insert(name.to_owned(), binding);
+                }
+                NodeType::MixinDefinition { .. } | NodeType::Rule { .. } => frame.mixins.push(node),
+                _ => {}
+            }
+        }
+        frame
+    }
+}
+
+/// compiled nodes of a block
+#[derive(Default)]
+struct Output {
+    /// declarations of the enclosing rule
+    decls: Vec<NodeType>,
+    /// rules and at-rules following the enclosing rule
+    nodes: Vec<NodeType>,
+}
+
+/// how much math is done in a value
+#[derive(Clone, Copy, PartialEq)]
+enum Math {
+    /// only variables, eg. in "calc()"
+    None,
+    /// "+", "-" and "*"
+    Default,
+    /// also "/", inside parens
+    Parens,
+}
+
+/// evaluated operand of a value
+#[derive(Debug, Clone)]
+enum Value {
+    /// number with its unit, "%" of a percentage, and its source text if it's not computed
+    Number {
+        value: f64,
+        unit: String,
+        text: Option<String>,
+    },
+    /// rgba with channels in 0-255 and alpha in 0-1, and its source text if it's not computed
+    Color {
+        rgba: [f64; 4],
+        text: Option<String>,
+    },
+    Text(String),
+}
+
+fn format_number(value: f64) -> String {
+    if (value - value.round()).abs() < 1e-9 {
+        return format!("{}", value.round() as i64);
+    }
+    let text = format!("{:.8}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+/// "#abc", "#aabbcc" with optional alpha
+fn parse_hex(name: &str) -> Option<[f64; 4]> {
+    if !name.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = match name.len() {
+        3 | 4 => name.bytes().flat_map(|byte| [byte, byte]).collect(),
+        6 | 8 => name.bytes().collect(),
+        _ => return None,
+    };
+    let mut rgba = [0.0, 0.0, 0.0, 1.0];
+    for (index, pair) in digits.chunks(2).enumerate() {
+        let channel = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()? as f64;
+        rgba[index] = if index == 3 { channel / 255.0 } else { channel };
+    }
+    Some(rgba)
+}
+
+fn format_color(rgba: &[f64; 4]) -> String {
+    // "76.49999999" of a hsl conversion is 76.5
+    let channel = |value: f64| ((value * 1e6).round() / 1e6).round().clamp(0.0, 255.0) as u8;
+    if rgba[3] >= 1.0 {
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            channel(rgba[0]),
+            channel(rgba[1]),
+            channel(rgba[2])
+        )
+    } else {
+        format!(
+            "rgba({}, {}, {}, {})",
+            channel(rgba[0]),
+            channel(rgba[1]),
+            channel(rgba[2]),
+            format_number(rgba[3].max(0.0))
+        )
+    }
+}
+
+/// hue in 0-360, saturation and lightness in 0-1
+fn to_hsl(rgba: &[f64; 4]) -> (f64, f64, f64) {
+    let (r, g, b) = (rgba[0] / 255.0, rgba[1] / 255.0, rgba[2] / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, lightness);
+    }
+    let delta = max - min;
+    let saturation = if lightness > 0.5 {
+        delta / (2.0 - max - min)
+    } else {
+        delta / (max + min)
+    };
+    let hue = if max == r {
+        (g - b) / delta + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    (hue * 60.0, saturation, lightness)
+}
+
+fn from_hsl(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> [f64; 4] {
+    let q = if lightness < 0.5 {
+        lightness * (1.0 + saturation)
+    } else {
+        lightness + saturation - lightness * saturation
+    };
+    let p = 2.0 * lightness - q;
+    let channel = |t: f64| {
+        let t = t.rem_euclid(1.0);
+        let value = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        value * 255.0
+    };
+    let hue = hue / 360.0;
+    [
+        channel(hue + 1.0 / 3.0),
+        channel(hue),
+        channel(hue - 1.0 / 3.0),
+        alpha,
+    ]
+}
+
+/// content of a quoted string, eg. "foo" of "\"foo\""
+fn unquote(text: &str) -> &str {
+    for quote in ['"', '\''] {
+        if text.len() >= 2 && text.starts_with(quote) && text.ends_with(quote) {
+            return &text[1..text.len() - 1];
+        }
+    }
+    text
+}
+
+fn lex(source: &str) -> Vec<Token> {
+    Lexer::new()
+        .lex_from_source(source)
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Comment(_, _)))
+        .collect()
+}
+
+/// evaluated text as an operand, eg. "10px" or "#fff"
+fn value_of(text: &str) -> Value {
+    let tokens = lex(text.trim());
+    match tokens.as_slice() {
+        [token @ Token::Number(number, _)] => Value::Number {
+            value: number.value,
+            unit: String::new(),
+            text: Some(token.raw()),
+        },
+        [token @ Token::Percentage(number, _)] => Value::Number {
+            value: number.value,
+            unit: String::from("%"),
+            text: Some(token.raw()),
+        },
+        [token @ Token::Dimension(number, unit, _)] => Value::Number {
+            value: number.value,
+            unit: unit.value.to_owned(),
+            text: Some(token.raw()),
+        },
+        [token @ Token::Hash(name, _, _)] => match parse_hex(&name.value) {
+            Some(rgba) => Value::Color {
+                rgba,
+                text: Some(token.raw()),
+            },
+            None => Value::Text(token.raw()),
+        },
+        _ => Value::Text(text.trim().to_owned()),
+    }
+}
+
+impl Value {
+    fn to_text(&self) -> String {
+        match self {
+            Value::Number {
+                text: Some(text), ..
+            }
+            | Value::Color {
+                text: Some(text), ..
+            }
+            | Value::Text(text) => text.to_owned(),
+            Value::Number { value, unit, .. } => format!("{}{}", format_number(*value), unit),
+            Value::Color { rgba, .. } => format_color(rgba),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Number { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn boolean(value: bool) -> Value {
+        Value::Text(String::from(if value { "true" } else { "false" }))
+    }
+}
+
+fn compute(operator: char, left: f64, right: f64) -> Option<f64> {
+    match operator {
+        '+' => Some(left + right),
+        '-' => Some(left - right),
+        '*' => Some(left * right),
+        '/' if right != 0.0 => Some(left / right),
+        _ => None,
+    }
+}
+
+/// "1px + 2" is "3px", the unit of the left operand wins
+fn operate(operator: char, left: &Value, right: &Value) -> Option<Value> {
+    match (left, right) {
+        (
+            Value::Number {
+                value: left,
+                unit: left_unit,
+                ..
+            },
+            Value::Number {
+                value: right,
+                unit: right_unit,
+                ..
+            },
+        ) => Some(Value::Number {
+            value: compute(operator, *left, *right)?,
+            unit: if left_unit.is_empty() {
+                right_unit.to_owned()
+            } else {
+                left_unit.to_owned()
+            },
+            text: None,
+        }),
+        (Value::Color { rgba: left, .. }, Value::Color { rgba: right, .. }) => {
+            let mut rgba = *left;
+            for channel in 0..3 {
+                rgba[channel] = compute(operator, left[channel], right[channel])?;
+            }
+            Some(Value::Color { rgba, text: None })
+        }
+        (Value::Color { rgba: color, .. }, Value::Number { value, .. }) => {
+            let mut rgba = *color;
+            for channel in 0..3 {
+                rgba[channel] = compute(operator, color[channel], *value)?;
+            }
+            Some(Value::Color { rgba, text: None })
+        }
+        (Value::Number { value, .. }, Value::Color { rgba: color, .. }) => {
+            let mut rgba = *color;
+            for channel in 0..3 {
+                rgba[channel] = compute(operator, *value, color[channel])?;
+            }
+            Some(Value::Color { rgba, text: None })
+        }
+        _ => None,
+    }
+}
+
+/// split at top-level commas, eg. of selectors or media queries
+fn split_list(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut from = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen(_) | Token::Function(_, _) | Token::OpenSquare(_) => depth += 1,
+            Token::CloseParen(_) | Token::CloseSquare(_) => depth -= 1,
+            Token::Comma(_) if depth == 0 => {
+                parts.push(&tokens[from..index]);
+                from = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[from..]);
+    parts
+}
+
+/// source text with whitespace collapsed to one space
+fn text_of(tokens: &[Token]) -> String {
+    let text: String = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Whitespace(_, _) => String::from(" "),
+            token => token.raw(),
+        })
+        .collect();
+    text.trim().to_owned()
+}
+
+/// flatten selectors of a nested rule, "&" is replaced by each parent, eg. "& + &" of
+/// "a, b" is "a + a, a + b, b + a, b + b", without "&" the parent is an ancestor
+fn join_selectors(parents: &[String], selector: &str) -> Vec<String> {
+    let tokens = lex(selector);
+    let mut selectors = vec![];
+    for part in split_list(&tokens) {
+        let segments: Vec<String> = part
+            .split(|token| matches!(token, Token::Delim('&', _)))
+            .map(|segment| {
+                segment
+                    .iter()
+                    .map(|token| match token {
+                        Token::Whitespace(_, _) => String::from(" "),
+                        token => token.raw(),
+                    })
+                    .collect()
+            })
+            .collect();
+        if parents.is_empty() {
+            selectors.push(segments.concat().trim().to_owned());
+            continue;
+        }
+        if segments.len() == 1 {
+            for parent in parents {
+                selectors.push(format!("{} {}", parent, segments[0].trim()));
+            }
+            continue;
+        }
+        let mut combinations = vec![segments[0].to_owned()];
+        for segment in &segments[1..] {
+            combinations = combinations
+                .iter()
+                .flat_map(|prefix| {
+                    parents
+                        .iter()
+                        .map(move |parent| format!("{}{}{}", prefix, parent, segment))
+                })
+                .collect();
+        }
+        selectors.extend(
+            combinations
+                .iter()
+                .map(|selector| selector.trim().to_owned()),
+        );
+    }
+    selectors
+}
+
+/// media queries of a nested "@media" inside another, joined by "and"
+fn join_media(outer: &str, inner: &str) -> String {
+    let outer_tokens = lex(outer);
+    let inner_tokens = lex(inner);
+    let mut queries = vec![];
+    for outer in split_list(&outer_tokens) {
+        for inner in split_list(&inner_tokens) {
+            queries.push(format!("{} and {}", text_of(outer), text_of(inner)));
+        }
+    }
+    queries.join(", ")
+}
+
+/// name of a node which can be called as a mixin, eg. ".m" of ".m() {}" or "#ns" of "#ns {}"
+fn mixin_name(node: &NodeType) -> Option<&str> {
+    match node {
+        NodeType::MixinDefinition { name, .. } => Some(name),
+        NodeType::Rule {
+            selector, guard, ..
+        } => {
+            let selector = match guard {
+                Some(_) => selector.split(" when").next()?,
+                None => selector,
+            }
+            .trim();
+            let name = selector.strip_prefix('.').or(selector.strip_prefix('#'))?;
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            {
+                Some(selector)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn rule(selectors: &[String], nodes: Vec<NodeType>, source: &Location) -> NodeType {
+    let selector = selectors.join(", ");
+    NodeType::Rule {
+        r#type: String::from("rule"),
+        selectors: parse_selector_list(&lex(&selector)),
+        selector,
+        guard: None,
+        nodes,
+        source: source.to_owned(),
+        raws: Raws::default(),
+    }
+}
+
+fn atrule(name: &str, params: String, nodes: Option<Vec<NodeType>>, source: &Location) -> NodeType {
+    NodeType::Atrule {
+        r#type: String::from("atrule"),
+        name: name.to_owned(),
+        prelude: parse_prelude(name, &lex(&params)),
+        params,
+        nodes,
+        source: source.to_owned(),
+        raws: Raws::default(),
+    }
+}
+
+fn decl(prop: String, value: String, important: Option<bool>, source: &Location) -> NodeType {
+    NodeType::Decl {
+        r#type: String::from("decl"),
+        prop,
+        values: parse_component_values(&lex(&value)),
+        value,
+        important,
+        source: source.to_owned(),
+        raws: Raws::default(),
+    }
+}
+
+#[derive(Default)]
+struct Compiler<'n> {
+    /// scopes from the root to the current block
+    frames: Vec<Frame<'n>>,
+    /// params of the enclosing "@media", nested ones are joined to it
+    media: Option<String>,
+    /// mixins or detached rulesets being expanded with their arguments
+    expanding: Vec<(*const NodeType, Arguments)>,
+    /// mixin calls expanded so far
+    expansions: usize,
+    /// value of "default()" in a guard
+    default: bool,
+    /// variables being evaluated with the index of their frame, to stop recursive definitions
+    evaluating: Vec<(String, usize)>,
+    /// source of the node being compiled, the span of errors in its selector, params or value
+    source: Location,
+    errors: Vec<CompileError>,
+}
+
+impl<'n> Compiler<'n> {
+    /// record an error once, a value may be evaluated again, eg. by a guard with "default()"
+    fn error(&mut self, kind: CompileErrorKind, message: String, span: &Location) {
+        let error = CompileError {
+            kind,
+            message,
+            span: span.to_owned(),
+        };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    /// report a variable which can't be resolved, eg. "@missing" or "@config[primary]"
+    fn unresolved(&mut self, name: &str, text: &str) {
+        let message = match self.binding(name) {
+            Some(_) => format!("can't resolve \"{}\"", text),
+            None => format!("undefined variable \"@{}\"", name),
+        };
+        let span = self.source.to_owned();
+        self.error(CompileErrorKind::UndefinedVariable, message, &span);
+    }
+
+    /// index of the nearest frame defining a variable, and its binding
+    fn binding(&self, name: &str) -> Option<(usize, Binding<'n>)> {
+        let index = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.variables.contains_key(name))?;
+        Some((index, self.frames[index].variables[name].to_owned()))
+    }
+
+    /// evaluated value of a variable, in the scope where it's defined
+    fn variable(&mut self, name: &str) -> Option<String> {
+        match self.binding(name)? {
+            (_, Binding::Value(value)) => Some(value),
+            (_, Binding::Ruleset(_)) => None,
+            (index, Binding::Lazy(source)) => {
+                let key = (name.to_owned(), index);
+                if self.evaluating.contains(&key) {
+                    return None;
+                }
+                self.evaluating.push(key);
+                let above = self.frames.split_off(index + 1);
+                let value = self.evaluate(&source);
+                self.frames.extend(above);
+                self.evaluating.pop();
+                Some(value)
+            }
+        }
+    }
+
+    /// value of a map lookup, eg. "@config[primary]" or "@config[@dark][primary]"
+    fn lookup(&mut self, name: &str, keys: &[String]) -> Option<String> {
+        let mut nodes = match self.binding(name)? {
+            (_, Binding::Ruleset(nodes)) => nodes,
+            _ => return None,
+        };
+        for (index, key) in keys.iter().enumerate() {
+            let last = index + 1 == keys.len();
+            let found = nodes.iter().rev().find_map(|node| match node {
+                NodeType::Decl { prop, value, .. } if prop == key => Some((Some(value), None)),
+                NodeType::VariableDecl {
+                    name, value, nodes, ..
+                } if key.strip_prefix('@') == Some(name) => Some((Some(value), nodes.as_deref())),
+                NodeType::Rule {
+                    selector, nodes, ..
+                } if selector == key => Some((None, Some(nodes.as_slice()))),
+                _ => None,
+            })?;
+            match found {
+                (Some(value), None) if last => return Some(self.evaluate(value)),
+                (_, Some(children)) if !last => nodes = children,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// replace "@{name}" with the unquoted value of the variable
+    fn interpolate(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("@{") {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let name = &rest[start + 2..end];
+            output.push_str(&rest[..start]);
+            match self.variable(name) {
+                Some(value) => output.push_str(unquote(&value)),
+                None => {
+                    self.unresolved(name, &rest[start..=end]);
+                    output.push_str(&rest[start..=end]);
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// evaluate variables, operations and functions of a value
+    fn evaluate(&mut self, source: &str) -> String {
+        let tokens = lex(source);
+        let mut index = 0;
+        self.list(&tokens, &mut index, Math::Default)
+    }
+
+    /// values until the end or an unmatched close bracket, the whitespace between them kept
+    fn list(&mut self, tokens: &[Token], index: &mut usize, math: Math) -> String {
+        let mut output = String::new();
+        let mut space = false;
+        while let Some(token) = tokens.get(*index) {
+            match token {
+                Token::Whitespace(_, _) => {
+                    space = true;
+                    *index += 1;
+                    continue;
+                }
+                Token::CloseParen(_) | Token::CloseSquare(_) | Token::CloseCurly(_) => break,
+                _ => {}
+            }
+            if space && !output.is_empty() {
+                output.push(' ');
+            }
+            space = false;
+            if let Token::Comma(_) = token {
+                output.push(',');
+                *index += 1;
+            } else {
+                let value = self.sum(tokens, index, math);
+                output.push_str(&value.to_text());
+            }
+        }
+        output
+    }
+
+    /// operator after the current operand, and the index of the operand after it
+    fn operator(
+        &self,
+        tokens: &[Token],
+        index: usize,
+        operators: &[char],
+        math: Math,
+    ) -> Option<(char, usize)> {
+        let mut next = index;
+        while let Some(Token::Whitespace(_, _)) = tokens.get(next) {
+            next += 1;
+        }
+        let operator = match tokens.get(next) {
+            Some(Token::Delim(operator, _)) if operators.contains(operator) => *operator,
+            _ => return None,
+        };
+        if math == Math::None || (operator == '/' && math != Math::Parens) {
+            return None;
+        }
+        let space_before = next > index;
+        next += 1;
+        let mut after = next;
+        while let Some(Token::Whitespace(_, _)) = tokens.get(after) {
+            after += 1;
+        }
+        // "a -b" is a list of two values
+        if operator == '-' && space_before && after == next {
+            return None;
+        }
+        Some((operator, after))
+    }
+
+    fn sum(&mut self, tokens: &[Token], index: &mut usize, math: Math) -> Value {
+        let mut left = self.product(tokens, index, math);
+        while let Some((operator, next)) = self.operator(tokens, *index, &['+', '-'], math) {
+            let saved = *index;
+            *index = next;
+            let right = self.product(tokens, index, math);
+            match operate(operator, &left, &right) {
+                Some(value) => left = value,
+                None => {
+                    *index = saved;
+                    break;
+                }
+            }
+        }
+        left
+    }
+
+    fn product(&mut self, tokens: &[Token], index: &mut usize, math: Math) -> Value {
+        let mut left = self.term(tokens, index, math);
+        while let Some((operator, next)) = self.operator(tokens, *index, &['*', '/'], math) {
+            let saved = *index;
+            *index = next;
+            let right = self.term(tokens, index, math);
+            match operate(operator, &left, &right) {
+                Some(value) => left = value,
+                None => {
+                    *index = saved;
+                    break;
+                }
+            }
+        }
+        left
+    }
+
+    /// tokens inside brackets until the close one, which is eaten
+    fn nested(&mut self, tokens: &[Token], index: &mut usize, math: Math) -> String {
+        let inner = self.list(tokens, index, math);
+        if *index < tokens.len() {
+            *index += 1;
+        }
+        inner
+    }
+
+    fn term(&mut self, tokens: &[Token], index: &mut usize, math: Math) -> Value {
+        while let Some(Token::Whitespace(_, _)) = tokens.get(*index) {
+            *index += 1;
+        }
+        let token = match tokens.get(*index) {
+            Some(token) => token,
+            None => return Value::Text(String::new()),
+        };
+        *index += 1;
+        match token {
+            Token::Number(_, _)
+            | Token::Percentage(_, _)
+            | Token::Dimension(_, _, _)
+            | Token::Hash(_, _, _) => value_of(&token.raw()),
+            Token::AtKeyword(name, _) => {
+                let start = *index - 1;
+                let mut keys = vec![];
+                while let Some(Token::OpenSquare(_)) = tokens.get(*index) {
+                    let close = match tokens[*index..]
+                        .iter()
+                        .position(|token| matches!(token, Token::CloseSquare(_)))
+                    {
+                        Some(close) => *index + close,
+                        None => break,
+                    };
+                    keys.push(text_of(&tokens[*index + 1..close]));
+                    *index = close + 1;
+                }
+                let value = if keys.is_empty() {
+                    self.variable(&name.value)
+                } else {
+                    self.lookup(&name.value, &keys)
+                };
+                match value {
+                    Some(value) => value_of(&value),
+                    // kept as it is, eg. "@config[primary]" of an undefined map
+                    None => {
+                        let text: String = tokens[start..*index]
+                            .iter()
+                            .map(|token| token.raw())
+                            .collect();
+                        self.unresolved(&name.value, &text);
+                        Value::Text(text)
+                    }
+                }
+            }
+            // variable variable, eg. "@@name"
+            Token::Delim('@', _) => match tokens.get(*index) {
+                Some(Token::AtKeyword(name, _)) => {
+                    *index += 1;
+                    let value = self
+                        .variable(&name.value)
+                        .and_then(|inner| self.variable(unquote(&inner)));
+                    match value {
+                        Some(value) => value_of(&value),
+                        None => {
+                            let text = format!("@@{}", name.value);
+                            self.unresolved(&name.value, &text);
+                            Value::Text(text)
+                        }
+                    }
+                }
+                _ => Value::Text(String::from("@")),
+            },
+            Token::Interpolation(name, _) => match self.variable(&name.value) {
+                Some(value) => Value::Text(unquote(&value).to_owned()),
+                None => {
+                    self.unresolved(&name.value, &token.raw());
+                    Value::Text(token.raw())
+                }
+            },
+            // escape, eg. ~"calc(100% - @{gutter})"
+            Token::Delim('~', _) => match tokens.get(*index) {
+                Some(Token::String(string, _)) => {
+                    *index += 1;
+                    Value::Text(self.interpolate(&string.value))
+                }
+                _ => Value::Text(String::from("~")),
+            },
+            Token::String(_, _) | Token::Url(_, _) => Value::Text(self.interpolate(&token.raw())),
+            Token::Function(name, _) => {
+                let lower = name.value.to_ascii_lowercase();
+                let inner_math = match lower.as_str() {
+                    // left to the browser
+                    "calc" | "var" | "env" | "min" | "max" | "clamp" => Math::None,
+                    _ if math == Math::None => Math::None,
+                    _ => Math::Default,
+                };
+                let args = self.nested(tokens, index, inner_math);
+                match self.function(&lower, &args) {
+                    Some(value) => value,
+                    None => Value::Text(format!("{}({})", name.raw, args)),
+                }
+            }
+            Token::OpenParen(_) => {
+                if math != Math::None {
+                    let start = *index;
+                    let value = self.sum(tokens, index, Math::Parens);
+                    while let Some(Token::Whitespace(_, _)) = tokens.get(*index) {
+                        *index += 1;
+                    }
+                    match (&value, tokens.get(*index)) {
+                        (Value::Number { .. }, Some(Token::CloseParen(_)))
+                        | (Value::Color { .. }, Some(Token::CloseParen(_))) => {
+                            *index += 1;
+                            return value;
+                        }
+                        _ => *index = start,
+                    }
+                }
+                let inner_math = if math == Math::None {
+                    Math::None
+                } else {
+                    Math::Parens
+                };
+                Value::Text(format!("({})", self.nested(tokens, index, inner_math)))
+            }
+            Token::OpenSquare(_) => Value::Text(format!("[{}]", self.nested(tokens, index, math))),
+            Token::OpenCurly(_) => Value::Text(format!("{{{}}}", self.nested(tokens, index, math))),
+            token => Value::Text(token.raw()),
+        }
+    }
+
+    /// built-in less functions, None for css functions
+    fn function(&mut self, name: &str, args: &str) -> Option<Value> {
+        let tokens = lex(args);
+        let args: Vec<Value> = split_list(&tokens)
+            .into_iter()
+            .map(|arg| value_of(&text_of(arg)))
+            .filter(|arg| !matches!(arg, Value::Text(text) if text.is_empty()))
+            .collect();
+        let first = args.first();
+        let unit = |unit: &str| {
+            Value::boolean(
+                matches!(first, Some(Value::Number { unit: found, .. }) if found == unit),
+            )
+        };
+        let rounded = |round: fn(f64) -> f64| match first {
+            Some(Value::Number { value, unit, .. }) => Some(Value::Number {
+                value: round(*value),
+                unit: unit.to_owned(),
+                text: None,
+            }),
+            _ => None,
+        };
+        let value = match name {
+            "default" => Value::boolean(self.default),
+            "iscolor" => Value::boolean(matches!(first, Some(Value::Color { .. }))),
+            "isnumber" => Value::boolean(matches!(first, Some(Value::Number { .. }))),
+            "isstring" => Value::boolean(
+                matches!(first, Some(Value::Text(text)) if text.starts_with('"') || text.starts_with('\'')),
+            ),
+            "iskeyword" => Value::boolean(matches!(first, Some(Value::Text(text))
+                if lex(text).len() == 1 && matches!(lex(text)[0], Token::Ident(_, _)))),
+            "isurl" => {
+                Value::boolean(matches!(first, Some(Value::Text(text)) if text.starts_with("url(")))
+            }
+            "ispixel" => unit("px"),
+            "isem" => unit("em"),
+            "ispercentage" => unit("%"),
+            "isunit" => match args.get(1) {
+                Some(expected) => unit(unquote(&expected.to_text())),
+                None => return None,
+            },
+            "e" => Value::Text(unquote(&first?.to_text()).to_owned()),
+            "percentage" => Value::Number {
+                value: first?.number()? * 100.0,
+                unit: String::from("%"),
+                text: None,
+            },
+            "unit" => Value::Number {
+                value: first?.number()?,
+                unit: args
+                    .get(1)
+                    .map(|unit| unquote(&unit.to_text()).to_owned())
+                    .unwrap_or_default(),
+                text: None,
+            },
+            "round" => rounded(f64::round)?,
+            "ceil" => rounded(f64::ceil)?,
+            "floor" => rounded(f64::floor)?,
+            "lighten" | "darken" | "fade" => {
+                let rgba = match first? {
+                    Value::Color { rgba, .. } => rgba,
+                    _ => return None,
+                };
+                let amount = args.get(1)?.number()? / 100.0;
+                let (hue, saturation, lightness) = to_hsl(rgba);
+                let rgba = match name {
+                    "lighten" => from_hsl(hue, saturation, (lightness + amount).min(1.0), rgba[3]),
+                    "darken" => from_hsl(hue, saturation, (lightness - amount).max(0.0), rgba[3]),
+                    _ => [rgba[0], rgba[1], rgba[2], amount.clamp(0.0, 1.0)],
+                };
+                Value::Color { rgba, text: None }
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// mixins matching the path of a call from the nearest scope, eg. ["#ns", ".m"]
+    fn find_mixins(&self, path: &[String]) -> Vec<&'n NodeType> {
+        for frame in self.frames.iter().rev() {
+            let mut found: Vec<&'n NodeType> = frame
+                .mixins
+                .iter()
+                .copied()
+                .filter(|node| mixin_name(node) == Some(path[0].as_str()))
+                .collect();
+            for segment in &path[1..] {
+                found = found
+                    .into_iter()
+                    .flat_map(|node| match node {
+                        NodeType::Rule { nodes, .. } | NodeType::MixinDefinition { nodes, .. } => {
+                            nodes.as_slice()
+                        }
+                        _ => &[],
+                    })
+                    .filter(|node| mixin_name(node) == Some(segment.as_str()))
+                    .collect();
+            }
+            if !found.is_empty() {
+                return found;
+            }
+        }
+        vec![]
+    }
+
+    /// frame of the params bound to evaluated arguments, None if the mixin doesn't match them
+    fn bind(&self, mixin: &'n NodeType, args: &[(Option<String>, String)]) -> Option<Frame<'n>> {
+        let (params, defaults, rest) = match mixin {
+            NodeType::MixinDefinition {
+                params,
+                defaults,
+                rest,
+                ..
+            } => (params, defaults, rest),
+            // a rule called as mixin has no params
+            _ if args.is_empty() => return Some(Frame::default()),
+            _ => return None,
+        };
+        let mut frame = Frame::default();
+        let mut bound = vec![None; params.len()];
+        for (name, value) in args {
+            if let Some(name) = name {
+                let index = params.iter().position(|param| param == name)?;
+                bound[index] = Some(value.to_owned());
+            }
+        }
+        let mut positional = args
+            .iter()
+            .filter(|(name, _)| name.is_none())
+            .map(|(_, value)| value.to_owned());
+        let mut arguments = vec![];
+        for (index, param) in params.iter().enumerate() {
+            let value = match bound[index].take() {
+                Some(value) => value,
+                None => match param.strip_prefix('@') {
+                    Some(_) => match (positional.next(), &defaults[index]) {
+                        (Some(value), _) => value,
+                        // defaults may use other params, evaluated in the mixin scope
+                        (None, Some(default)) => {
+                            frame
+                                .variables
+                                .insert(param[1..].to_owned(), Binding::Lazy(default.to_owned()));
+                            arguments.push(default.to_owned());
+                            continue;
+                        }
+                        (None, None) => return None,
+                    },
+                    // a value to match, eg. "dark" of ".m(dark; @color)"
+                    None => {
+                        let value = positional.next()?;
+                        if unquote(&value) != unquote(param) {
+                            return None;
+                        }
+                        arguments.push(value);
+                        continue;
+                    }
+                },
+            };
+            if let Some(name) = param.strip_prefix('@') {
+                frame
+                    .variables
+                    .insert(name.to_owned(), Binding::Value(value.to_owned()));
+            }
+            arguments.push(value);
+        }
+        let remaining: Vec<String> = positional.collect();
+        match rest {
+            Some(name) => {
+                if let Some(name) = name.strip_prefix('@') {
+                    frame
+                        .variables
+                        .insert(name.to_owned(), Binding::Value(remaining.join(" ")));
+                }
+                arguments.extend(remaining);
+            }
+            None if !remaining.is_empty() => return None,
+            None => {}
+        }
+        frame.variables.insert(
+            String::from("arguments"),
+            Binding::Lazy(arguments.join(" ")),
+        );
+        Some(frame)
+    }
+
+    /// start expanding a mixin or detached ruleset, false if it's already being expanded
+    /// with the same arguments, which never ends, or the limits are reached
+    fn enter(
+        &mut self,
+        key: *const NodeType,
+        args: &[(Option<String>, String)],
+        name: &str,
+        source: &Location,
+    ) -> bool {
+        let recursive = self
+            .expanding
+            .iter()
+            .any(|(other, other_args)| *other == key && other_args == args);
+        if recursive {
+            let message = format!("\"{}\" calls itself with the same arguments", name);
+            self.error(CompileErrorKind::RecursiveMixin, message, source);
+            return false;
+        }
+        if self.frames.len() >= MAX_DEPTH || self.expansions >= MAX_EXPANSIONS {
+            let message = format!(
+                "mixin calls nest or expand too much, \"{}\" is dropped",
+                name
+            );
+            self.error(CompileErrorKind::TooManyExpansions, message, source);
+            return false;
+        }
+        self.expansions += 1;
+        self.expanding.push((key, args.to_vec()));
+        true
+    }
+
+    fn call(&mut self, node: &'n NodeType, selectors: &[String], output: &mut Output) {
+        let (name, namespace, args, important, source) = match node {
+            NodeType::MixinCall {
+                name,
+                namespace,
+                args,
+                important,
+                source,
+                ..
+            } => (name, namespace, args, *important, source),
+            _ => return,
+        };
+        self.source = source.to_owned();
+        let mut expanded = Output::default();
+        if let Some(ruleset) = name.strip_prefix('@') {
+            // detached ruleset call, eg. "@detached();"
+            match self.binding(ruleset) {
+                Some((_, Binding::Ruleset(nodes))) => {
+                    if self.enter(nodes.as_ptr(), &[], name, source) {
+                        self.block(nodes, selectors, &mut expanded);
+                        self.expanding.pop();
+                    }
+                }
+                _ => self.error(
+                    CompileErrorKind::UndefinedVariable,
+                    format!("undefined detached ruleset \"{}\"", name),
+                    source,
+                ),
+            }
+        } else {
+            // arguments are evaluated in the scope of the call
+            let args: Arguments = args
+                .iter()
+                .map(|arg| (arg.name.to_owned(), self.evaluate(&arg.value)))
+                .collect();
+            let mut path = namespace.to_owned();
+            path.push(name.to_owned());
+            let mixins = self.find_mixins(&path);
+            let mut matched = vec![];
+            for &mixin in mixins.iter() {
+                let frame = match self.bind(mixin, &args) {
+                    Some(frame) => frame,
+                    None => continue,
+                };
+                let guard = match mixin {
+                    NodeType::MixinDefinition { guard, .. } | NodeType::Rule { guard, .. } => guard,
+                    _ => &None,
+                };
+                self.frames.push(frame);
+                // "default()" is true only if no other mixin matches
+                let (normal, default) = match guard {
+                    Some(guard) => {
+                        let normal = guard.evaluate(self);
+                        self.default = true;
+                        let default = guard.evaluate(self);
+                        self.default = false;
+                        (normal, default)
+                    }
+                    None => (true, true),
+                };
+                let frame = self.frames.pop().unwrap_or_default();
+                matched.push((mixin, frame, normal, default));
+            }
+            if matched.is_empty() {
+                let message = if mixins.is_empty() {
+                    format!("undefined mixin \"{}\"", path.join(" "))
+                } else {
+                    format!("no mixin \"{}\" takes these arguments", path.join(" "))
+                };
+                self.error(CompileErrorKind::UnmatchedMixin, message, source);
+            }
+            let any_normal = matched.iter().any(|(_, _, normal, _)| *normal);
+            for (mixin, frame, normal, default) in matched {
+                if !normal && (any_normal || !default) {
+                    continue;
+                }
+                let nodes = match mixin {
+                    NodeType::MixinDefinition { nodes, .. } | NodeType::Rule { nodes, .. } => nodes,
+                    _ => continue,
+                };
+                if !self.enter(mixin, &args, &path.join(" "), source) {
+                    continue;
+                }
+                self.frames.push(frame);
+                self.block(nodes, selectors, &mut expanded);
+                self.frames.pop();
+                self.expanding.pop();
+            }
+        }
+        if important {
+            for node in expanded.decls.iter_mut() {
+                if let NodeType::Decl { important, .. } = node {
+                    *important = Some(true);
+                }
+            }
+        }
+        output.decls.extend(expanded.decls);
+        output.nodes.extend(expanded.nodes);
+    }
+
+    fn block(&mut self, nodes: &'n [NodeType], selectors: &[String], output: &mut Output) {
+        self.frames.push(Frame::new(nodes));
+        for node in nodes {
+            self.node(node, selectors, output);
+        }
+        self.frames.pop();
+    }
+
+    fn atrule(
+        &mut self,
+        name: &str,
+        params: &str,
+        nodes: &'n Option<Vec<NodeType>>,
+        source: &Location,
+        selectors: &[String],
+        output: &mut Output,
+    ) {
+        self.source = source.to_owned();
+        let lower = name.to_ascii_lowercase();
+        let params = match lower.as_str() {
+            "media" | "supports" | "container" => self.evaluate(params),
+            _ => self.interpolate(params),
+        };
+        let children = match nodes {
+            Some(children) => children,
+            None => {
+                output.nodes.push(atrule(name, params, None, source));
+                return;
+            }
+        };
+        if lower.ends_with("keyframes") || DESCRIPTOR_ATRULES.contains(&lower.as_str()) {
+            let mut inner = Output::default();
+            self.block(children, &[], &mut inner);
+            let mut body = inner.decls;
+            body.extend(inner.nodes);
+            output.nodes.push(atrule(name, params, Some(body), source));
+            return;
+        }
+        // conditional at-rules bubble up to the top level, wrapping the enclosing selector
+        let media = lower == "media";
+        let params = match (&self.media, media) {
+            (Some(outer), true) => join_media(outer, &params),
+            _ => params,
+        };
+        let outer = if media {
+            self.media.replace(params.to_owned())
+        } else {
+            self.media.to_owned()
+        };
+        let mut inner = Output::default();
+        self.block(children, selectors, &mut inner);
+        self.media = outer;
+        let mut body = vec![];
+        if !inner.decls.is_empty() {
+            if selectors.is_empty() {
+                body.extend(inner.decls);
+            } else {
+                body.push(rule(selectors, inner.decls, source));
+            }
+        }
+        let mut bubbled = vec![];
+        for node in inner.nodes {
+            match &node {
+                NodeType::Atrule { name, .. } if media && name.eq_ignore_ascii_case("media") => {
+                    bubbled.push(node)
+                }
+                _ => body.push(node),
+            }
+        }
+        if !body.is_empty() {
+            output.nodes.push(atrule(name, params, Some(body), source));
+        }
+        output.nodes.extend(bubbled);
+    }
+
+    /// a declaration with its prop interpolated and its value evaluated
+    fn decl(&mut self, node: &NodeType, output: &mut Output) {
+        if let NodeType::Decl {
+            prop,
+            value,
+            important,
+            source,
+            ..
+        } = node
+        {
+            self.source = source.to_owned();
+            let prop = self.interpolate(prop);
+            let value = if prop.starts_with("--") {
+                self.interpolate(value)
+            } else {
+                self.evaluate(value)
+            };
+            output.decls.push(decl(prop, value, *important, source));
+        }
+    }
+
+    /// a nested rule, flattened with the selectors of its parents
+    fn rule(&mut self, node: &'n NodeType, selectors: &[String], output: &mut Output) {
+        if let NodeType::Rule {
+            selector,
+            guard,
+            nodes,
+            source,
+            ..
+        } = node
+        {
+            self.source = source.to_owned();
+            if let Some(guard) = guard {
+                if !guard.evaluate(self) {
+                    return;
+                }
+            }
+            let mut tokens = lex(selector);
+            if guard.is_some() {
+                if let Some(index) = search_when(&tokens) {
+                    tokens.truncate(index);
+                }
+            }
+            let selector = self.interpolate(&text_of(&tokens));
+            let selectors = join_selectors(selectors, &selector);
+            let mut inner = Output::default();
+            self.block(nodes, &selectors, &mut inner);
+            if !inner.decls.is_empty() {
+                output.nodes.push(rule(&selectors, inner.decls, source));
+            }
+            output.nodes.extend(inner.nodes);
+        }
+    }
+
+    // arms are kept in their own functions, this one is on the stack once for every nested block
+    fn node(&mut self, node: &'n NodeType, selectors: &[String], output: &mut Output) {
+        match node {
+            // collected into the frame of the block
+            NodeType::Root | NodeType::VariableDecl { .. } | NodeType::MixinDefinition { .. } => {}
+            NodeType::Comment { inline: true, .. } => {}
+            NodeType::Comment {
+                r#type,
+                text,
+                inline,
+                source,
+                ..
+            } => {
+                let comment = NodeType::Comment {
+                    r#type: r#type.to_owned(),
+                    text: text.to_owned(),
+                    inline: *inline,
+                    source: source.to_owned(),
+                    raws: Raws::default(),
+                };
+                if selectors.is_empty() {
+                    output.nodes.push(comment);
+                } else {
+                    output.decls.push(comment);
+                }
+            }
+            NodeType::Decl { .. } => self.decl(node, output),
+            NodeType::Rule { .. } => self.rule(node, selectors, output),
+            NodeType::Keyframe {
+                selector,
+                nodes,
+                source,
+                ..
+            } => {
+                let mut inner = Output::default();
+                self.block(nodes, &[], &mut inner);
+                self.source = source.to_owned();
+                let selector = self.interpolate(selector);
+                output.nodes.push(rule(&[selector], inner.decls, source));
+            }
+            NodeType::Atrule {
+                name,
+                params,
+                nodes,
+                source,
+                ..
+            } => self.atrule(name, params, nodes, source, selectors, output),
+            NodeType::MixinCall { .. } => self.call(node, selectors, output),
+        }
+    }
+}
+
+impl GuardEvaluator for Compiler<'_> {
+    fn truthy(&mut self, operand: &str) -> bool {
+        self.evaluate(operand) == "true"
+    }
+
+    fn compare(&mut self, left: &str, right: &str) -> Option<Ordering> {
+        let left = value_of(&self.evaluate(left));
+        let right = value_of(&self.evaluate(right));
+        match (&left, &right) {
+            (
+                Value::Number {
+                    value: left,
+                    unit: left_unit,
+                    ..
+                },
+                Value::Number {
+                    value: right,
+                    unit: right_unit,
+                    ..
+                },
+            ) if left_unit == right_unit || left_unit.is_empty() || right_unit.is_empty() => {
+                left.partial_cmp(right)
+            }
+            (Value::Color { rgba: left, .. }, Value::Color { rgba: right, .. })
+                if left == right =>
+            {
+                Some(Ordering::Equal)
+            }
+            (Value::Text(left), Value::Text(right)) if unquote(left) == unquote(right) => {
+                Some(Ordering::Equal)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// compiled nodes with the errors met on the way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileResult {
+    pub nodes: Vec<NodeType>,
+    pub errors: Vec<CompileError>,
+}
+
+/// compile less nodes to plain css rules, at-rules and declarations:
+/// variables and mixins are resolved, nesting is flattened and "@media" bubbles to the top level
+pub fn compile(nodes: &[NodeType]) -> CompileResult {
+    let mut compiler = Compiler::default();
+    let mut output = Output::default();
+    compiler.block(nodes, &[], &mut output);
+    let mut nodes = output.decls;
+    nodes.extend(output.nodes);
+    CompileResult {
+        nodes,
+        errors: compiler.errors,
+    }
+}
@@ -0,0 +1,487 @@
+use super::generator::{Generator, Options};
+use super::lexer::{Lexer, Token};
+use super::parser::{NodeType, ParseError, Parser, RawValue, Raws};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quote {
+    Double,
+    Single,
+    /// keep quotes of strings as they are
+    Preserve,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatOptions {
+    /// spaces of an indent level
+    pub indent_width: usize,
+    /// indent with a tab instead of spaces
+    pub use_tabs: bool,
+    /// put each selector of a list on its own line
+    pub selector_per_line: bool,
+    /// blank line before rules and at-rules with block
+    pub blank_line_between_rules: bool,
+    pub quote: Quote,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 4,
+            use_tabs: false,
+            selector_per_line: true,
+            blank_line_between_rules: true,
+            quote: Quote::Double,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    Selector,
+    Params,
+    Value,
+}
+
+fn is_combinator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Delim('>', _) | Token::Delim('+', _) | Token::Delim('~', _)
+    )
+}
+
+struct Formatter<'o> {
+    options: &'o FormatOptions,
+}
+
+impl Formatter<'_> {
+    fn indent(&self, depth: usize) -> String {
+        if self.options.use_tabs {
+            "\t".repeat(depth)
+        } else {
+            " ".repeat(self.options.indent_width * depth)
+        }
+    }
+
+    /// whitespace between two tokens of a selector, params or value
+    fn separator(
+        &self,
+        prev: &Token,
+        next: &Token,
+        part: Part,
+        nesting: usize,
+        space: bool,
+        depth: usize,
+    ) -> String {
+        match (prev, next) {
+            // a "//" comment ends at the line break
+            (Token::Comment(text, _), _) if text.starts_with("//") => self.comment_break(part, depth),
+            (_, Token::Comma(_))
+            | (_, Token::CloseParen(_))
+            | (_, Token::CloseSquare(_))
+            | (Token::OpenParen(_), _)
+            | (Token::Function(_, _), _)
+            | (Token::OpenSquare(_), _) => String::new(),
+            (Token::Comma(_), _)
+                if part == Part::Selector && nesting == 0 && self.options.selector_per_line =>
+            {
+                format!("\n{}", self.indent(depth))
+            }
+            (Token::Comma(_), _) => String::from(" "),
+            (prev, next)
+                if part == Part::Selector
+                    && nesting == 0
+                    && (is_combinator(prev) || is_combinator(next)) =>
+            {
+                String::from(" ")
+            }
+            // media feature, eg. "(max-width: 100px)"
+            (_, Token::Colon(_)) if part == Part::Params && nesting > 0 => String::new(),
+            (Token::Colon(_), _) if part == Part::Params && nesting > 0 => String::from(" "),
+            _ if space => String::from(" "),
+            _ => String::new(),
+        }
+    }
+
+    /// string with the preferred quote, unless it contains the quote
+    fn quote(&self, raw: &str) -> String {
+        let preferred = match self.options.quote {
+            Quote::Double => '"',
+            Quote::Single => '\'',
+            Quote::Preserve => return raw.to_owned(),
+        };
+        let current = match raw.chars().next() {
+            Some(quote) if raw.len() >= 2 && raw.ends_with(quote) => quote,
+            // unclosed at end of input
+            _ => return raw.to_owned(),
+        };
+        let inner = &raw[1..raw.len() - 1];
+        if current == preferred || inner.contains(preferred) {
+            raw.to_owned()
+        } else {
+            format!("{}{}{}", preferred, inner, preferred)
+        }
+    }
+
+    fn text(&self, source: &str, part: Part, depth: usize) -> String {
+        let tokens = Lexer::new().lex_from_source(source);
+        let mut output = String::new();
+        let mut prev: Option<&Token> = None;
+        let mut space = false;
+        // depth of "()" and "[]"
+        let mut nesting: usize = 0;
+        for token in tokens.iter() {
+            if let Token::Whitespace(_, _) = token {
+                space = true;
+                continue;
+            }
+            if let Some(prev) = prev {
+                output.push_str(&self.separator(prev, token, part, nesting, space, depth));
+            }
+            space = false;
+            match token {
+                Token::Hash(name, _, _)
+                    if part == Part::Value
+                        && [3, 4, 6, 8].contains(&name.raw.len())
+                        && name.raw.bytes().all(|b| b.is_ascii_hexdigit()) =>
+                {
+                    output.push('#');
+                    output.push_str(&name.raw.to_ascii_lowercase());
+                }
+                Token::String(string, _) => output.push_str(&self.quote(&string.raw)),
+                _ => output.push_str(&token.raw()),
+            }
+            match token {
+                Token::OpenParen(_) | Token::Function(_, _) | Token::OpenSquare(_) => nesting += 1,
+                Token::CloseParen(_) | Token::CloseSquare(_) => nesting = nesting.saturating_sub(1),
+                _ => {}
+            }
+            prev = Some(token);
+        }
+        if let Some(Token::Comment(text, _)) = prev {
+            if text.starts_with("//") {
+                output.push_str(&self.comment_break(part, depth));
+            }
+        }
+        output
+    }
+
+    /// line break after a "//" comment inside a selector, params or value
+    fn comment_break(&self, part: Part, depth: usize) -> String {
+        match part {
+            Part::Value => format!("\n{}", self.indent(depth + 1)),
+            _ => format!("\n{}", self.indent(depth)),
+        }
+    }
+
+    /// format a value and its raw text with comments
+    fn raw_text(
+        &self,
+        value: &str,
+        raw: &Option<RawValue>,
+        part: Part,
+        depth: usize,
+    ) -> (String, Option<RawValue>) {
+        let value = self.text(value, part, depth);
+        match raw {
+            Some(raw) => {
+                let raw = self.text(&raw.raw, part, depth);
+                (value.to_owned(), Some(RawValue { value, raw }))
+            }
+            None => (value, None),
+        }
+    }
+
+    fn nodes(&self, nodes: &[NodeType], depth: usize) -> Vec<NodeType> {
+        let mut formatted = vec![];
+        for (index, node) in nodes.iter().enumerate() {
+            let (raws, is_comment, is_block) = match node {
+                NodeType::Root => continue,
+                NodeType::Rule { raws, .. }
+                | NodeType::Keyframe { raws, .. }
+                | NodeType::MixinDefinition { raws, .. } => (raws, false, true),
+                NodeType::MixinCall { raws, .. } => (raws, false, false),
+                NodeType::Atrule { raws, nodes, .. } => (raws, false, nodes.is_some()),
+                NodeType::VariableDecl { raws, nodes, .. } => (raws, false, nodes.is_some()),
+                NodeType::Decl { raws, .. } => (raws, false, false),
+                NodeType::Comment { raws, .. } => (raws, true, false),
+            };
+            let newlines = raws
+                .before
+                .as_ref()
+                .map_or(0, |before| before.matches('\n').count());
+            let after_comment = index > 0 && matches!(nodes[index - 1], NodeType::Comment { .. });
+            let before = if index == 0 {
+                if depth == 0 {
+                    String::new()
+                } else {
+                    format!("\n{}", self.indent(depth))
+                }
+            } else if is_comment && newlines == 0 {
+                // comment at the end of line, eg. "color: red; // note"
+                String::from(" ")
+            } else if newlines >= 2
+                || (is_block && self.options.blank_line_between_rules && !after_comment)
+            {
+                format!("\n\n{}", self.indent(depth))
+            } else {
+                format!("\n{}", self.indent(depth))
+            };
+            formatted.push(self.node(node, before, depth));
+        }
+        formatted
+    }
+
+    fn block_raws(&self, before: String, nodes: &[NodeType], depth: usize) -> Raws {
+        let after = if nodes.is_empty() {
+            String::new()
+        } else {
+            format!("\n{}", self.indent(depth))
+        };
+        Raws {
+            before: Some(before),
+            between: Some(String::from(" ")),
+            after: Some(after),
+            semicolon: Some(true),
+            ..Raws::default()
+        }
+    }
+
+    fn node(&self, node: &NodeType, before: String, depth: usize) -> NodeType {
+        match node {
+            NodeType::Root => NodeType::Root,
+            NodeType::Comment {
+                r#type,
+                text,
+                inline,
+                source,
+                raws,
+            } => NodeType::Comment {
+                r#type: r#type.to_owned(),
+                text: text.to_owned(),
+                inline: *inline,
+                source: source.to_owned(),
+                raws: Raws {
+                    before: Some(before),
+                    ..raws.to_owned()
+                },
+            },
+            NodeType::Rule {
+                r#type,
+                selector,
+                selectors,
+                guard,
+                nodes,
+                source,
+                raws,
+            } => {
+                let nodes = self.nodes(nodes, depth + 1);
+                let (selector, raw) =
+                    self.raw_text(selector, &raws.selector, Part::Selector, depth);
+                NodeType::Rule {
+                    r#type: r#type.to_owned(),
+                    selector,
+                    selectors: selectors.to_owned(),
+                    guard: guard.to_owned(),
+                    source: source.to_owned(),
+                    raws: Raws {
+                        selector: raw,
+                        ..self.block_raws(before, &nodes, depth)
+                    },
+                    nodes,
+                }
+            }
+            NodeType::Keyframe {
+                r#type,
+                selector,
+                offsets,
+                ranges,
+                nodes,
+                source,
+                raws,
+            } => {
+                let nodes = self.nodes(nodes, depth + 1);
+                let (selector, raw) =
+                    self.raw_text(selector, &raws.selector, Part::Selector, depth);
+                NodeType::Keyframe {
+                    r#type: r#type.to_owned(),
+                    selector,
+                    offsets: offsets.to_owned(),
+                    ranges: ranges.to_owned(),
+                    source: source.to_owned(),
+                    raws: Raws {
+                        selector: raw,
+                        ..self.block_raws(before, &nodes, depth)
+                    },
+                    nodes,
+                }
+            }
+            NodeType::MixinDefinition {
+                r#type,
+                name,
+                params,
+                defaults,
+                rest,
+                guard,
+                selector,
+                nodes,
+                source,
+                raws,
+            } => {
+                let nodes = self.nodes(nodes, depth + 1);
+                let (selector, raw) =
+                    self.raw_text(selector, &raws.selector, Part::Selector, depth);
+                NodeType::MixinDefinition {
+                    r#type: r#type.to_owned(),
+                    name: name.to_owned(),
+                    params: params.to_owned(),
+                    defaults: defaults.to_owned(),
+                    rest: rest.to_owned(),
+                    guard: guard.to_owned(),
+                    selector,
+                    source: source.to_owned(),
+                    raws: Raws {
+                        selector: raw,
+                        ..self.block_raws(before, &nodes, depth)
+                    },
+                    nodes,
+                }
+            }
+            NodeType::MixinCall {
+                r#type,
+                name,
+                namespace,
+                args,
+                important,
+                selector,
+                source,
+                raws,
+            } => {
+                let (selector, raw) =
+                    self.raw_text(selector, &raws.selector, Part::Selector, depth);
+                NodeType::MixinCall {
+                    r#type: r#type.to_owned(),
+                    name: name.to_owned(),
+                    namespace: namespace.to_owned(),
+                    args: args.to_owned(),
+                    important: *important,
+                    selector,
+                    source: source.to_owned(),
+                    raws: Raws {
+                        before: Some(before),
+                        selector: raw,
+                        ..Raws::default()
+                    },
+                }
+            }
+            NodeType::Atrule {
+                r#type,
+                name,
+                params,
+                prelude,
+                nodes,
+                source,
+                raws,
+            } => {
+                let nodes = nodes.as_ref().map(|nodes| self.nodes(nodes, depth + 1));
+                let mut new_raws = self.block_raws(before, nodes.as_deref().unwrap_or(&[]), depth);
+                let (params, raw) = self.raw_text(params, &raws.params, Part::Params, depth);
+                let after_name = if params.is_empty() { "" } else { " " };
+                new_raws.after_name = Some(String::from(after_name));
+                new_raws.params = raw;
+                if nodes.is_none() {
+                    new_raws.between = Some(String::new());
+                }
+                NodeType::Atrule {
+                    r#type: r#type.to_owned(),
+                    name: name.to_owned(),
+                    params,
+                    prelude: prelude.to_owned(),
+                    nodes,
+                    source: source.to_owned(),
+                    raws: new_raws,
+                }
+            }
+            NodeType::VariableDecl {
+                r#type,
+                name,
+                value,
+                values,
+                important,
+                nodes,
+                source,
+                raws,
+            } => {
+                let nodes = nodes.as_ref().map(|nodes| self.nodes(nodes, depth + 1));
+                let (value, raw) = self.raw_text(value, &raws.value, Part::Value, depth);
+                let raws = match &nodes {
+                    Some(nodes) => self.block_raws(before, nodes, depth),
+                    None => Raws {
+                        before: Some(before),
+                        between: Some(String::from(": ")),
+                        value: raw,
+                        ..Raws::default()
+                    },
+                };
+                NodeType::VariableDecl {
+                    r#type: r#type.to_owned(),
+                    name: name.to_owned(),
+                    value,
+                    values: values.to_owned(),
+                    important: important.to_owned(),
+                    nodes,
+                    source: source.to_owned(),
+                    raws,
+                }
+            }
+            NodeType::Decl {
+                r#type,
+                prop,
+                value,
+                values,
+                important,
+                source,
+                raws,
+            } => {
+                let (value, raw) = if prop.starts_with("--") {
+                    // custom property values are kept as they are
+                    (value.to_owned(), raws.value.to_owned())
+                } else {
+                    self.raw_text(value, &raws.value, Part::Value, depth)
+                };
+                NodeType::Decl {
+                    r#type: r#type.to_owned(),
+                    prop: prop.to_owned(),
+                    value,
+                    values: values.to_owned(),
+                    important: important.to_owned(),
+                    source: source.to_owned(),
+                    raws: Raws {
+                        before: Some(before),
+                        between: Some(String::from(": ")),
+                        value: raw,
+                        ..Raws::default()
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// print source in a consistent style, keep comments
+///
+/// formatting a formatted source gives it back unchanged,
+/// source with errors is not formatted to not lose any of it
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    let tokens = Lexer::new().lex_from_source(source);
+    let nodes = Parser::new(&tokens).parse_strict()?;
+    let nodes = Formatter { options }.nodes(&nodes, 0);
+    let raws = Raws {
+        after: Some(String::from(if nodes.is_empty() { "" } else { "\n" })),
+        semicolon: Some(true),
+        ..Raws::default()
+    };
+    Ok(Generator::new(&Options::default()).generate(&nodes, &raws))
+}
@@ -0,0 +1,392 @@
+mod source_map;
+
+use super::lexer::Position;
+use super::parser::{NodeType, RawValue, Raws};
+use serde::{Deserialize, Serialize};
+
+pub use source_map::{Mapping, SourceMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Options {
+    /// print with the defaults below even if nodes have raws
+    pub ignore_raws: bool,
+    /// indent of a nesting level for nodes without raws
+    pub indent: String,
+    /// generate a source map
+    pub map: Option<MapOptions>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ignore_raws: false,
+            indent: String::from("    "),
+            map: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MapOptions {
+    /// append the map to output as a base64 `sourceMappingURL` comment
+    pub inline: bool,
+    /// file name of the input, "<input css>" by default
+    pub from: Option<String>,
+    /// file name of the output
+    pub to: Option<String>,
+    /// source text of the input, added as `sourcesContent`
+    pub content: Option<String>,
+    /// source map of the input, eg. from a previous compiler, output maps to its sources
+    pub prev: Option<SourceMap>,
+}
+
+/// print nodes back to CSS(less)
+///
+/// raws of nodes are kept as they are, so a parsed source is given back byte-for-byte,
+/// nodes without raws (eg. created by a transformer) are printed with the defaults
+pub struct Generator<'o> {
+    options: &'o Options,
+    output: String,
+    depth: usize,
+    /// zero-based line and UTF-16 column of the end of output
+    line: usize,
+    column: usize,
+    last_cr: bool,
+    mappings: Vec<Mapping>,
+}
+
+impl<'o> Generator<'o> {
+    pub fn new(options: &'o Options) -> Generator<'o> {
+        Generator {
+            options,
+            output: String::new(),
+            depth: 0,
+            line: 0,
+            column: 0,
+            last_cr: false,
+            mappings: vec![],
+        }
+    }
+
+    /// print root nodes with the `after` and `semicolon` raws of root
+    pub fn generate(&mut self, nodes: &[NodeType], raws: &Raws) -> String {
+        self.body(nodes, raws);
+        let after = self.raw(&raws.after, String::new());
+        self.push(&after);
+        if let Some(MapOptions { inline: true, .. }) = self.options.map {
+            if let Some(map) = self.source_map() {
+                let comment = map.to_inline_comment();
+                self.push("\n");
+                self.push(&comment);
+            }
+        }
+        std::mem::take(&mut self.output)
+    }
+
+    /// source map of the generated output, if enabled in options
+    pub fn source_map(&self) -> Option<SourceMap> {
+        let options = self.options.map.as_ref()?;
+        let source = options
+            .from
+            .to_owned()
+            .unwrap_or_else(|| String::from("<input css>"));
+        let prev = match &options.prev {
+            Some(prev) => prev,
+            None => {
+                return Some(SourceMap::new(
+                    options.to.to_owned(),
+                    vec![source],
+                    options
+                        .content
+                        .to_owned()
+                        .map(|content| vec![Some(content)]),
+                    &self.mappings,
+                ));
+            }
+        };
+        // map through the input source map to its sources
+        let prev_mappings = prev.decode();
+        let mappings: Vec<Mapping> = self
+            .mappings
+            .iter()
+            .filter_map(|mapping| {
+                let original = source_map::lookup(
+                    &prev_mappings,
+                    mapping.original_line,
+                    mapping.original_column,
+                )?;
+                Some(Mapping {
+                    source: original.source,
+                    original_line: original.original_line,
+                    original_column: original.original_column,
+                    ..mapping.to_owned()
+                })
+            })
+            .collect();
+        let mut map = SourceMap::new(
+            options.to.to_owned(),
+            prev.sources.to_owned(),
+            prev.sources_content.to_owned(),
+            &mappings,
+        );
+        map.source_root = prev.source_root.to_owned();
+        Some(map)
+    }
+
+    fn push(&mut self, string: &str) {
+        if self.options.map.is_some() {
+            for c in string.chars() {
+                match c {
+                    '\n' if self.last_cr => {}
+                    '\n' | '\r' | '\x0C' => {
+                        self.line += 1;
+                        self.column = 0;
+                    }
+                    c => self.column += c.len_utf16(),
+                }
+                self.last_cr = c == '\r';
+            }
+        }
+        self.output.push_str(string);
+    }
+
+    /// map the end of output to a position in source
+    fn mark(&mut self, position: &Position) {
+        if self.options.map.is_some() {
+            self.mappings.push(Mapping {
+                generated_line: self.line,
+                generated_column: self.column,
+                source: 0,
+                original_line: position.line,
+                original_column: position.column_utf16,
+            });
+        }
+    }
+
+    fn raw(&self, raw: &Option<String>, default: String) -> String {
+        match raw {
+            Some(raw) if !self.options.ignore_raws => raw.to_owned(),
+            _ => default,
+        }
+    }
+
+    /// raw text of a selector, value or params, unless the value has been changed since parsing
+    fn raw_value(&self, raw: &Option<RawValue>, value: &str) -> String {
+        match raw {
+            Some(raw) if !self.options.ignore_raws && raw.value == value => raw.raw.to_owned(),
+            _ => value.to_owned(),
+        }
+    }
+
+    fn indent(&self) -> String {
+        self.options.indent.repeat(self.depth)
+    }
+
+    fn before(&mut self, raws: &Raws, first: bool) {
+        let default = if first && self.depth == 0 {
+            String::new()
+        } else {
+            format!("\n{}", self.indent())
+        };
+        let before = self.raw(&raws.before, default);
+        self.push(&before);
+    }
+
+    fn body(&mut self, nodes: &[NodeType], raws: &Raws) {
+        // comments after the last declaration don't take its ";"
+        let last = nodes
+            .iter()
+            .rposition(|node| !matches!(node, NodeType::Comment { .. }));
+        let semicolon = match raws.semicolon {
+            Some(semicolon) if !self.options.ignore_raws => semicolon,
+            _ => true,
+        };
+        for (index, node) in nodes.iter().enumerate() {
+            self.node(node, index == 0, Some(index) != last || semicolon);
+        }
+    }
+
+    fn block(&mut self, nodes: &[NodeType], raws: &Raws) {
+        let between = self.raw(&raws.between, String::from(" "));
+        self.push(&between);
+        self.push("{");
+        self.depth += 1;
+        self.body(nodes, raws);
+        self.depth -= 1;
+        let default = if nodes.is_empty() {
+            String::new()
+        } else {
+            format!("\n{}", self.indent())
+        };
+        let after = self.raw(&raws.after, default);
+        self.push(&after);
+        self.push("}");
+    }
+
+    fn node(&mut self, node: &NodeType, first: bool, semicolon: bool) {
+        let source = match node {
+            NodeType::Root => return,
+            NodeType::Rule { source, raws, .. }
+            | NodeType::Keyframe { source, raws, .. }
+            | NodeType::Atrule { source, raws, .. }
+            | NodeType::Decl { source, raws, .. }
+            | NodeType::VariableDecl { source, raws, .. }
+            | NodeType::MixinDefinition { source, raws, .. }
+            | NodeType::MixinCall { source, raws, .. }
+            | NodeType::Comment { source, raws, .. } => {
+                self.before(raws, first);
+                source
+            }
+        };
+        self.mark(&source.start);
+        self.node_body(node, semicolon);
+        self.mark(&source.end);
+    }
+
+    fn node_body(&mut self, node: &NodeType, semicolon: bool) {
+        match node {
+            NodeType::Root => {}
+            NodeType::Rule {
+                selector,
+                nodes,
+                raws,
+                ..
+            }
+            | NodeType::Keyframe {
+                selector,
+                nodes,
+                raws,
+                ..
+            }
+            | NodeType::MixinDefinition {
+                selector,
+                nodes,
+                raws,
+                ..
+            } => {
+                let selector = self.raw_value(&raws.selector, selector);
+                self.push(&selector);
+                self.block(nodes, raws);
+            }
+            NodeType::Atrule {
+                name,
+                params,
+                nodes,
+                raws,
+                ..
+            } => {
+                self.push("@");
+                self.push(name);
+                let default = if params.is_empty() { "" } else { " " };
+                let after_name = self.raw(&raws.after_name, String::from(default));
+                let params = self.raw_value(&raws.params, params);
+                self.push(&after_name);
+                self.push(&params);
+                match nodes {
+                    Some(nodes) => self.block(nodes, raws),
+                    None => {
+                        let between = self.raw(&raws.between, String::new());
+                        self.push(&between);
+                        if semicolon {
+                            self.push(";");
+                        }
+                    }
+                }
+            }
+            NodeType::VariableDecl {
+                name,
+                value,
+                important,
+                nodes,
+                raws,
+                ..
+            } => {
+                self.push("@");
+                self.push(name);
+                match nodes {
+                    // detached ruleset, `between` is after ":"
+                    Some(nodes) => {
+                        self.push(":");
+                        self.block(nodes, raws);
+                    }
+                    None => {
+                        let between = self.raw(&raws.between, String::from(": "));
+                        let value = self.raw_value(&raws.value, value);
+                        self.push(&between);
+                        self.push(&value);
+                        if let Some(true) = important {
+                            let important =
+                                self.raw(&raws.important, String::from(" !important"));
+                            self.push(&important);
+                        }
+                        if semicolon {
+                            self.push(";");
+                        }
+                    }
+                }
+            }
+            NodeType::Decl {
+                prop,
+                value,
+                important,
+                raws,
+                ..
+            } => {
+                let between = self.raw(&raws.between, String::from(": "));
+                let value = self.raw_value(&raws.value, value);
+                self.push(prop);
+                self.push(&between);
+                self.push(&value);
+                if let Some(true) = important {
+                    let important = self.raw(&raws.important, String::from(" !important"));
+                    self.push(&important);
+                }
+                if semicolon {
+                    self.push(";");
+                }
+            }
+            NodeType::MixinCall {
+                selector,
+                important,
+                raws,
+                ..
+            } => {
+                let selector = self.raw_value(&raws.selector, selector);
+                self.push(&selector);
+                if *important {
+                    let important = self.raw(&raws.important, String::from(" !important"));
+                    self.push(&important);
+                }
+                if semicolon {
+                    self.push(";");
+                }
+            }
+            NodeType::Comment {
+                text, inline, raws, ..
+            } => {
+                let left = self.raw(&raws.left, String::from(" "));
+                if *inline {
+                    let right = self.raw(&raws.right, String::new());
+                    self.push("//");
+                    self.push(&left);
+                    self.push(text);
+                    self.push(&right);
+                } else {
+                    let right = self.raw(&raws.right, String::from(" "));
+                    self.push("/*");
+                    self.push(&left);
+                    self.push(text);
+                    self.push(&right);
+                    self.push("*/");
+                }
+            }
+        }
+    }
+}
+
+/// print nodes back to CSS(less)
+pub fn stringify(nodes: &[NodeType], options: &Options) -> String {
+    Generator::new(options).generate(nodes, &Raws::default())
+}
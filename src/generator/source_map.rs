@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// source map v3, <https://sourcemaps.info/spec.html>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMap {
+    pub version: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_root: Option<String>,
+    pub sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources_content: Option<Vec<Option<String>>>,
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub mappings: String,
+}
+
+/// a generated position with its original position, lines and UTF-16 columns are zero-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub generated_line: usize,
+    pub generated_column: usize,
+    /// index in `sources`
+    pub source: usize,
+    pub original_line: usize,
+    pub original_column: usize,
+}
+
+fn encode_vlq(output: &mut String, value: i64) {
+    let mut vlq = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = vlq & 0b11111;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000; // continuation bit
+        }
+        output.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+/// decode a segment of mappings, return None on invalid characters,
+/// a value too long or a value left without its last digit
+fn decode_vlq(segment: &str) -> Option<Vec<i64>> {
+    let mut values = vec![];
+    let mut value = 0;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = BASE64.iter().position(|&b| b == c)? as i64;
+        if shift > 32 {
+            return None;
+        }
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 == 0 {
+            let negative = value & 1 == 1;
+            value >>= 1;
+            values.push(if negative { -value } else { value });
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 5;
+        }
+    }
+    if shift > 0 {
+        return None;
+    }
+    Some(values)
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64[(n >> (18 - i * 6) & 0b111111) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+impl SourceMap {
+    /// build a source map from mappings sorted by generated position
+    pub fn new(
+        file: Option<String>,
+        sources: Vec<String>,
+        sources_content: Option<Vec<Option<String>>>,
+        mappings: &[Mapping],
+    ) -> SourceMap {
+        let mut encoded = String::new();
+        let mut line = 0;
+        let mut column = 0;
+        let mut source = 0;
+        let mut original_line = 0;
+        let mut original_column = 0;
+        for (index, mapping) in mappings.iter().enumerate() {
+            if mapping.generated_line > line {
+                for _ in line..mapping.generated_line {
+                    encoded.push(';');
+                }
+                line = mapping.generated_line;
+                column = 0;
+            } else if index > 0 {
+                encoded.push(',');
+            }
+            encode_vlq(&mut encoded, mapping.generated_column as i64 - column);
+            encode_vlq(&mut encoded, mapping.source as i64 - source);
+            encode_vlq(&mut encoded, mapping.original_line as i64 - original_line);
+            encode_vlq(
+                &mut encoded,
+                mapping.original_column as i64 - original_column,
+            );
+            column = mapping.generated_column as i64;
+            source = mapping.source as i64;
+            original_line = mapping.original_line as i64;
+            original_column = mapping.original_column as i64;
+        }
+        SourceMap {
+            version: 3,
+            file,
+            source_root: None,
+            sources,
+            sources_content,
+            names: vec![],
+            mappings: encoded,
+        }
+    }
+
+    /// mappings with original positions, sorted by generated position
+    ///
+    /// decoding stops at the first invalid segment
+    pub fn decode(&self) -> Vec<Mapping> {
+        let mut mappings = vec![];
+        let mut source = 0;
+        let mut original_line = 0;
+        let mut original_column = 0;
+        for (line, segments) in self.mappings.split(';').enumerate() {
+            let mut column = 0;
+            for segment in segments.split(',').filter(|segment| !segment.is_empty()) {
+                let values = match decode_vlq(segment) {
+                    Some(values) => values,
+                    None => return mappings,
+                };
+                column += values[0];
+                if values.len() < 4 {
+                    // generated position without original
+                    continue;
+                }
+                source += values[1];
+                original_line += values[2];
+                original_column += values[3];
+                if column < 0 || source < 0 || original_line < 0 || original_column < 0 {
+                    return mappings;
+                }
+                mappings.push(Mapping {
+                    generated_line: line,
+                    generated_column: column as usize,
+                    source: source as usize,
+                    original_line: original_line as usize,
+                    original_column: original_column as usize,
+                });
+            }
+        }
+        mappings
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// `/*# sourceMappingURL=data:... */` comment embedding the map
+    pub fn to_inline_comment(&self) -> String {
+        format!(
+            "/*# sourceMappingURL=data:application/json;base64,{} */",
+            encode_base64(self.to_json().as_bytes())
+        )
+    }
+}
+
+/// find the original position of a generated position from sorted mappings,
+/// by the closest mapping before it on the same line
+pub fn lookup(mappings: &[Mapping], line: usize, column: usize) -> Option<&Mapping> {
+    let index = mappings.partition_point(|mapping| {
+        (mapping.generated_line, mapping.generated_column) <= (line, column)
+    });
+    let mapping = mappings.get(index.checked_sub(1)?)?;
+    if mapping.generated_line == line {
+        Some(mapping)
+    } else {
+        None
+    }
+}
@@ -1,135 +1,784 @@
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-lazy_static! {
-    // 'testValue\''
-    static ref SINGLE_QUOTE_STRING: Regex = Regex::new(r#"^"(?:[^"\\]|\\.)*""#).unwrap();
-    // "testValue\""
-    static ref DOUBLE_QUOTE_STRING: Regex = Regex::new(r"^'(?:[^'\\]|\\.)*'").unwrap();
-    // #999 #999FFF #abc
-    static ref HEX_VALUE: Regex = Regex::new(r"^#[0-9a-fA-F]{3,6}").unwrap();
-    // -test-value1 -test1 .test1 #test1 test1
-    static ref WORD: Regex = Regex::new(r"^(\.|-|#)?[a-zA-Z]{1,}(-?[a-zA-Z0-9]){0,}").unwrap();
-    // 000
-    static ref NUMBER: Regex = Regex::new(r"^\d+").unwrap();
-    //
-    static ref SPACE: Regex = Regex::new(r"^\s{1,}").unwrap();
-    // {}()[]
-    static ref PAREN: Regex = Regex::new(r"^[\{\}\(\)\[\]]").unwrap();
-    // \!@,:;#&%+-*/.
-    static ref PUNCTUATOR: Regex = Regex::new(r"^[!@,:;#&%\+\-\*/\.]").unwrap();
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// zero based line and column, with the byte offset from the start of the source
+///
+/// `column` counts UTF-8 bytes, `column_utf16` counts UTF-16 code units as javascript
+/// strings and LSP clients do
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Position {
-    line: usize,
-    column: usize,
+    pub line: usize,
+    pub column: usize,
+    pub column_utf16: usize,
+    pub offset: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     pub start: Position,
     pub end: Position,
 }
 
+/// type flag of a `<hash-token>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HashType {
+    /// the name is a valid identifier, eg. "#main"
+    Id,
+    /// eg. "#999"
+    Unrestricted,
+}
+
+/// type flag of a numeric token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NumberType {
+    /// eg. "10", "-3"
+    Integer,
+    /// eg. "0.5", "1e3"
+    Number,
+}
+
+/// the number part of `<number-token>`, `<percentage-token>` and `<dimension-token>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Numeric {
+    /// source text of the number, eg. "+.5e2"
+    pub repr: String,
+    pub value: f64,
+    pub number_type: NumberType,
+    /// explicit "+" or "-" in front of the number
+    pub sign: Option<char>,
+}
+
+impl Numeric {
+    fn from_repr(repr: String) -> Numeric {
+        let number_type = if repr.contains(['.', 'e', 'E']) {
+            NumberType::Number
+        } else {
+            NumberType::Integer
+        };
+        let sign = match repr.chars().next() {
+            Some(c) if c == '+' || c == '-' => Some(c),
+            _ => None,
+        };
+        Numeric {
+            value: repr.parse().unwrap_or(0.0),
+            repr,
+            number_type,
+            sign,
+        }
+    }
+}
+
+/// text of a token, with escapes resolved in `value` and kept in `raw`
+///
+/// eg. `.a\:hover` gives an ident with value "a:hover" and raw "a\:hover"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Text {
+    pub value: String,
+    pub raw: String,
+}
+
+/// tokens of the CSS Syntax Module Level 3 tokenizer, plus comments
+///
+/// payloads keep the source text of the token, so that the token list can be
+/// printed back without loss
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Token {
-    String(String, Location),
-    Paren(String, Location),
-    Word(String, Location),
-    Punctuator(String, Location),
-    Space(String, Location),
-    Hex(String, Location),
-    Number(String, Location),
-    Other(String),
-    EndLine(Location),
+    /// `<ident-token>`
+    Ident(Text, Location),
+    /// `<function-token>`, the name without "("
+    Function(Text, Location),
+    /// `<at-keyword-token>`, the name without "@"
+    AtKeyword(Text, Location),
+    /// `<hash-token>`, the name without "#"
+    Hash(Text, HashType, Location),
+    /// `<string-token>`, the value without quotes, the raw text with its quotes
+    String(Text, Location),
+    /// `<bad-string-token>`, a string broken by a newline
+    BadString(String, Location),
+    /// `<url-token>`, the value inside "url()", the raw text of the whole "url(...)"
+    Url(Text, Location),
+    /// `<bad-url-token>`
+    BadUrl(String, Location),
+    /// `<delim-token>`
+    Delim(char, Location),
+    /// `<number-token>`
+    Number(Numeric, Location),
+    /// `<percentage-token>`, the number without "%"
+    Percentage(Numeric, Location),
+    /// `<dimension-token>`, the number and the unit
+    Dimension(Numeric, Text, Location),
+    /// `<whitespace-token>`
+    Whitespace(String, Location),
+    /// `<CDO-token>` "<!--"
+    Cdo(Location),
+    /// `<CDC-token>` "-->"
+    Cdc(Location),
+    Colon(Location),
+    Semicolon(Location),
+    Comma(Location),
+    OpenSquare(Location),
+    CloseSquare(Location),
+    OpenParen(Location),
+    CloseParen(Location),
+    OpenCurly(Location),
+    CloseCurly(Location),
+    /// "/* */" comment, or less "//" comment
+    Comment(String, Location),
+    /// less interpolation "@{name}", the name without "@{}"
+    Interpolation(Text, Location),
+}
+
+impl Token {
+    pub fn location(&self) -> &Location {
+        match self {
+            Token::Ident(_, location)
+            | Token::Function(_, location)
+            | Token::AtKeyword(_, location)
+            | Token::Interpolation(_, location)
+            | Token::Hash(_, _, location)
+            | Token::String(_, location)
+            | Token::BadString(_, location)
+            | Token::Url(_, location)
+            | Token::BadUrl(_, location)
+            | Token::Delim(_, location)
+            | Token::Number(_, location)
+            | Token::Percentage(_, location)
+            | Token::Dimension(_, _, location)
+            | Token::Whitespace(_, location)
+            | Token::Cdo(location)
+            | Token::Cdc(location)
+            | Token::Colon(location)
+            | Token::Semicolon(location)
+            | Token::Comma(location)
+            | Token::OpenSquare(location)
+            | Token::CloseSquare(location)
+            | Token::OpenParen(location)
+            | Token::CloseParen(location)
+            | Token::OpenCurly(location)
+            | Token::CloseCurly(location)
+            | Token::Comment(_, location) => location,
+        }
+    }
+
+    /// the source text of the token
+    pub fn raw(&self) -> String {
+        match self {
+            Token::Ident(text, _) | Token::String(text, _) | Token::Url(text, _) => {
+                text.raw.to_owned()
+            }
+            Token::BadString(string, _)
+            | Token::BadUrl(string, _)
+            | Token::Whitespace(string, _)
+            | Token::Comment(string, _) => string.to_owned(),
+            Token::Function(name, _) => format!("{}(", name.raw),
+            Token::AtKeyword(name, _) => format!("@{}", name.raw),
+            Token::Interpolation(name, _) => format!("@{{{}}}", name.raw),
+            Token::Hash(name, _, _) => format!("#{}", name.raw),
+            Token::Delim(c, _) => c.to_string(),
+            Token::Number(number, _) => number.repr.to_owned(),
+            Token::Percentage(number, _) => format!("{}%", number.repr),
+            Token::Dimension(number, unit, _) => format!("{}{}", number.repr, unit.raw),
+            Token::Cdo(_) => String::from("<!--"),
+            Token::Cdc(_) => String::from("-->"),
+            Token::Colon(_) => String::from(":"),
+            Token::Semicolon(_) => String::from(";"),
+            Token::Comma(_) => String::from(","),
+            Token::OpenSquare(_) => String::from("["),
+            Token::CloseSquare(_) => String::from("]"),
+            Token::OpenParen(_) => String::from("("),
+            Token::CloseParen(_) => String::from(")"),
+            Token::OpenCurly(_) => String::from("{"),
+            Token::CloseCurly(_) => String::from("}"),
+        }
+    }
+}
+
+/// a preprocessed code point, "\r\n", "\r" and "\x0C" become "\n", NUL becomes U+FFFD
+///
+/// `offset` and `len` point back to the source bytes
+#[derive(Debug, Clone, Copy)]
+struct CodePoint {
+    c: char,
+    offset: usize,
+    len: usize,
+}
+
+const EOF: char = '\0';
+
+fn preprocess(source: &str) -> Vec<CodePoint> {
+    let mut code_points = Vec::with_capacity(source.len());
+    let mut chars = source.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let code_point = match c {
+            '\r' => {
+                if let Some((_, '\n')) = chars.peek() {
+                    chars.next();
+                    CodePoint {
+                        c: '\n',
+                        offset,
+                        len: 2,
+                    }
+                } else {
+                    CodePoint {
+                        c: '\n',
+                        offset,
+                        len: 1,
+                    }
+                }
+            }
+            '\x0C' => CodePoint {
+                c: '\n',
+                offset,
+                len: 1,
+            },
+            '\0' => CodePoint {
+                c: '\u{FFFD}',
+                offset,
+                len: 1,
+            },
+            c => CodePoint {
+                c,
+                offset,
+                len: c.len_utf8(),
+            },
+        };
+        code_points.push(code_point);
+    }
+    code_points
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == '\n' || c == '\t' || c == ' '
+}
+
+fn is_hex_digit(c: char) -> bool {
+    c.is_ascii_hexdigit()
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || (c as u32) >= 0x80
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\u{0}'..='\u{8}' | '\u{B}' | '\u{E}'..='\u{1F}' | '\u{7F}')
+}
+
+/// two code points are a valid escape
+fn is_valid_escape(first: char, second: char) -> bool {
+    first == '\\' && second != '\n'
+}
+
+/// three code points would start an ident sequence
+fn starts_ident(first: char, second: char, third: char) -> bool {
+    match first {
+        '-' => is_ident_start(second) || second == '-' || is_valid_escape(second, third),
+        '\\' => is_valid_escape(first, second),
+        c => is_ident_start(c),
+    }
+}
+
+/// three code points would start a number
+fn starts_number(first: char, second: char, third: char) -> bool {
+    match first {
+        '+' | '-' => second.is_ascii_digit() || (second == '.' && third.is_ascii_digit()),
+        '.' => second.is_ascii_digit(),
+        c => c.is_ascii_digit(),
+    }
 }
 
 pub struct LexerImpl {
+    code_points: Vec<CodePoint>,
+    /// index of the next code point
+    index: usize,
+    source_len: usize,
     column: usize,
+    column_utf16: usize,
     line: usize,
+    /// byte offset of the current line
+    line_offset: usize,
     pub tokens: Vec<Token>,
 }
 
+impl Default for LexerImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LexerImpl {
     pub fn new() -> LexerImpl {
         LexerImpl {
+            code_points: Vec::new(),
+            index: 0,
+            source_len: 0,
             column: 0,
+            column_utf16: 0,
             line: 0,
+            line_offset: 0,
             tokens: Vec::new(),
         }
     }
 
-    fn match_rule<'a, F: (FnOnce(String, Location) -> Token)>(
-        &mut self,
-        string: &'a str,
-        rule: &Regex,
-        construct: F,
-    ) -> Option<(Token, &'a str)> {
-        if let Some(matched) = rule.find(string) {
-            let start_pos = matched.start();
-            let end_pos = matched.end();
-            let rest = &string[end_pos..];
-            let start = Position {
-                column: start_pos + self.column,
-                line: self.line,
-            };
-            let end = Position {
-                column: end_pos + self.column,
-                line: self.line,
-            };
-            self.column += end_pos;
-            let token = construct(String::from(matched.as_str()), Location { start, end });
-            Some((token, rest))
+    fn offset(&self) -> usize {
+        match self.code_points.get(self.index) {
+            Some(code_point) => code_point.offset,
+            None => self.source_len,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            column: self.column,
+            column_utf16: self.column_utf16,
+            line: self.line,
+            offset: self.offset(),
+        }
+    }
+
+    /// peek the nth code point after the current one, EOF at the end of input
+    fn peek(&self, n: usize) -> char {
+        match self.code_points.get(self.index + n) {
+            Some(code_point) => code_point.c,
+            None => EOF,
+        }
+    }
+
+    fn is_eof(&self) -> bool {
+        self.index >= self.code_points.len()
+    }
+
+    /// consume the current code point, counting lines on the way
+    fn consume(&mut self) -> char {
+        let code_point = self.code_points[self.index];
+        self.index += 1;
+        if code_point.c == '\n' {
+            self.line += 1;
+            self.line_offset = code_point.offset + code_point.len;
+            self.column = 0;
+            self.column_utf16 = 0;
+        } else {
+            self.column = self.offset() - self.line_offset;
+            self.column_utf16 += code_point.c.len_utf16();
+        }
+        code_point.c
+    }
+
+    fn consume_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while !self.is_eof() && predicate(self.peek(0)) {
+            self.consume();
+        }
+    }
+
+    /// source text between two byte offsets
+    fn slice(source: &str, start: &Position, end: &Position) -> String {
+        source[start.offset..end.offset].to_owned()
+    }
+
+    /// resolve escapes of the code points between two indexes
+    ///
+    /// an escaped newline, only valid in strings, is removed
+    fn unescape(&self, from: usize, to: usize) -> String {
+        let mut value = String::new();
+        let mut i = from;
+        while i < to {
+            let c = self.code_points[i].c;
+            i += 1;
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            if i >= to {
+                // "\" at the end of input
+                value.push('\u{FFFD}');
+                continue;
+            }
+            let next = self.code_points[i].c;
+            if next == '\n' {
+                i += 1;
+            } else if is_hex_digit(next) {
+                let mut hex = String::new();
+                while i < to && hex.len() < 6 && is_hex_digit(self.code_points[i].c) {
+                    hex.push(self.code_points[i].c);
+                    i += 1;
+                }
+                if i < to && is_whitespace(self.code_points[i].c) {
+                    i += 1;
+                }
+                let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+                let c = match code {
+                    0 => None,
+                    code => char::from_u32(code),
+                };
+                value.push(c.unwrap_or('\u{FFFD}'));
+            } else {
+                value.push(next);
+                i += 1;
+            }
+        }
+        value
+    }
+
+    /// text from the code point at `from` and source position `start` to the current one
+    fn text(&self, source: &str, from: usize, start: &Position) -> Text {
+        Text {
+            value: self.unescape(from, self.index),
+            raw: Self::slice(source, start, &self.position()),
+        }
+    }
+
+    /// consume an escaped code point, the "\" is already consumed
+    fn consume_escape(&mut self) {
+        if is_hex_digit(self.peek(0)) {
+            let mut digits = 0;
+            while digits < 6 && is_hex_digit(self.peek(0)) {
+                self.consume();
+                digits += 1;
+            }
+            if is_whitespace(self.peek(0)) {
+                self.consume();
+            }
+        } else if !self.is_eof() {
+            self.consume();
+        }
+    }
+
+    /// consume an ident sequence
+    fn consume_ident_sequence(&mut self) {
+        loop {
+            let c = self.peek(0);
+            if is_ident_char(c) && !self.is_eof() {
+                self.consume();
+            } else if is_valid_escape(c, self.peek(1)) {
+                self.consume();
+                self.consume_escape();
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// consume a number, only its extent matters here
+    fn consume_number(&mut self) {
+        if self.peek(0) == '+' || self.peek(0) == '-' {
+            self.consume();
+        }
+        self.consume_while(|c| c.is_ascii_digit());
+        if self.peek(0) == '.' && self.peek(1).is_ascii_digit() {
+            self.consume();
+            self.consume_while(|c| c.is_ascii_digit());
+        }
+        let (e, sign, digit) = (self.peek(0), self.peek(1), self.peek(2));
+        if e == 'e' || e == 'E' {
+            if sign.is_ascii_digit() {
+                self.consume();
+                self.consume_while(|c| c.is_ascii_digit());
+            } else if (sign == '+' || sign == '-') && digit.is_ascii_digit() {
+                self.consume();
+                self.consume();
+                self.consume_while(|c| c.is_ascii_digit());
+            }
+        }
+    }
+
+    fn consume_numeric(&mut self, source: &str, start: Position) -> Token {
+        self.consume_number();
+        let number = Numeric::from_repr(Self::slice(source, &start, &self.position()));
+        if starts_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            let unit_start = self.position();
+            let from = self.index;
+            self.consume_ident_sequence();
+            let unit = self.text(source, from, &unit_start);
+            let end = self.position();
+            Token::Dimension(number, unit, Location { start, end })
+        } else if self.peek(0) == '%' {
+            self.consume();
+            let end = self.position();
+            Token::Percentage(number, Location { start, end })
         } else {
-            None
-        }
-    }
-
-    pub fn loop_line_for_token(&mut self, line: &String) {
-        let mut current = line.to_owned();
-        self.column = 0;
-        'loop_for_token: loop {
-            let result = self
-                .match_rule(&current, &HEX_VALUE, Token::Hex)
-                .or_else(|| self.match_rule(&current, &WORD, Token::Word))
-                .or_else(|| self.match_rule(&current, &NUMBER, Token::Number))
-                .or_else(|| self.match_rule(&current, &SPACE, Token::Space))
-                .or_else(|| self.match_rule(&current, &SINGLE_QUOTE_STRING, Token::String))
-                .or_else(|| self.match_rule(&current, &DOUBLE_QUOTE_STRING, Token::String))
-                .or_else(|| self.match_rule(&current, &PAREN, Token::Paren))
-                .or_else(|| self.match_rule(&current, &PUNCTUATOR, Token::Punctuator));
-
-            match result {
-                Some((token, rest)) => {
-                    current = String::from(rest);
-                    self.tokens.push(token);
+            let end = self.position();
+            Token::Number(number, Location { start, end })
+        }
+    }
+
+    /// consume the rest of a bad url, including the closing ")"
+    fn consume_bad_url(&mut self) {
+        while !self.is_eof() {
+            let c = self.consume();
+            if c == ')' {
+                return;
+            }
+            if is_valid_escape(c, self.peek(0)) {
+                self.consume_escape();
+            }
+        }
+    }
+
+    /// consume an unquoted url, "url(" is already consumed
+    fn consume_url(&mut self, source: &str, start: Position) -> Token {
+        self.consume_while(is_whitespace);
+        let from = self.index;
+        let to;
+        loop {
+            if self.is_eof() {
+                to = self.index;
+                break;
+            }
+            let c = self.consume();
+            match c {
+                ')' => {
+                    to = self.index - 1;
+                    break;
+                }
+                c if is_whitespace(c) => {
+                    to = self.index - 1;
+                    self.consume_while(is_whitespace);
+                    if self.is_eof() {
+                        break;
+                    }
+                    if self.peek(0) == ')' {
+                        self.consume();
+                        break;
+                    }
+                    self.consume_bad_url();
+                    let end = self.position();
+                    return Token::BadUrl(Self::slice(source, &start, &end), Location { start, end });
+                }
+                '"' | '\'' | '(' => {
+                    self.consume_bad_url();
+                    let end = self.position();
+                    return Token::BadUrl(Self::slice(source, &start, &end), Location { start, end });
+                }
+                c if is_non_printable(c) => {
+                    self.consume_bad_url();
+                    let end = self.position();
+                    return Token::BadUrl(Self::slice(source, &start, &end), Location { start, end });
                 }
-                None => {
-                    if !current.is_empty() {
-                        // no matched but there are still text in line, pass it as Other token
-                        let text = current[0..1].to_owned();
-                        let rest = &current[1..];
-                        self.column += 1;
-                        current = String::from(rest);
-                        let token = Token::Other(text);
-                        self.tokens.push(token);
+                '\\' => {
+                    if is_valid_escape(c, self.peek(0)) {
+                        self.consume_escape();
                     } else {
-                        // no matched at the end, break this line
-                        break 'loop_for_token;
+                        self.consume_bad_url();
+                        let end = self.position();
+                        return Token::BadUrl(
+                            Self::slice(source, &start, &end),
+                            Location { start, end },
+                        );
                     }
                 }
+                _ => {}
             }
         }
-        let start = Position {
-            column: self.column,
-            line: self.line,
+        let end = self.position();
+        let text = Text {
+            value: self.unescape(from, to),
+            raw: Self::slice(source, &start, &end),
         };
-        let end = Position {
-            column: self.column,
-            line: self.line,
+        Token::Url(text, Location { start, end })
+    }
+
+    /// consume an ident, function or url token
+    fn consume_ident_like(&mut self, source: &str, start: Position) -> Token {
+        let from = self.index;
+        self.consume_ident_sequence();
+        let name_end = self.position();
+        let name = self.text(source, from, &start);
+        if self.peek(0) == '(' {
+            self.consume();
+            if name.value.eq_ignore_ascii_case("url") {
+                // a quoted url is a plain function token, "url(" followed by a string
+                let mut n = 0;
+                while is_whitespace(self.peek(n)) {
+                    n += 1;
+                }
+                let next = self.peek(n);
+                if next != '"' && next != '\'' {
+                    return self.consume_url(source, start);
+                }
+            }
+            let end = self.position();
+            Token::Function(name, Location { start, end })
+        } else {
+            Token::Ident(name, Location { start, end: name_end })
+        }
+    }
+
+    /// consume a string token, the opening quote is already consumed
+    fn consume_string(&mut self, source: &str, start: Position, quote: char) -> Token {
+        let from = self.index;
+        let to;
+        loop {
+            if self.is_eof() {
+                to = self.index;
+                break;
+            }
+            let c = self.peek(0);
+            if c == quote {
+                to = self.index;
+                self.consume();
+                break;
+            }
+            if c == '\n' {
+                // the newline is not part of the bad string
+                let end = self.position();
+                return Token::BadString(Self::slice(source, &start, &end), Location { start, end });
+            }
+            self.consume();
+            if c == '\\' && !self.is_eof() {
+                if self.peek(0) == '\n' {
+                    // escaped newline, the string continues on the next line
+                    self.consume();
+                } else {
+                    self.consume_escape();
+                }
+            }
+        }
+        let end = self.position();
+        let text = Text {
+            value: self.unescape(from, to),
+            raw: Self::slice(source, &start, &end),
         };
-        self.tokens.push(Token::EndLine(Location { start, end }));
-        self.line += 1;
+        Token::String(text, Location { start, end })
+    }
+
+    fn consume_comment(&mut self, source: &str, start: Position) -> Token {
+        // "/*" is already consumed
+        while !self.is_eof() {
+            if self.peek(0) == '*' && self.peek(1) == '/' {
+                self.consume();
+                self.consume();
+                break;
+            }
+            self.consume();
+        }
+        let end = self.position();
+        Token::Comment(Self::slice(source, &start, &end), Location { start, end })
+    }
+
+    /// "@{name}" of less interpolation starts at the current code point
+    fn starts_interpolation(&self) -> bool {
+        let mut n = 2;
+        while self.index + n < self.code_points.len() && is_ident_char(self.peek(n)) {
+            n += 1;
+        }
+        n > 2 && self.peek(n) == '}'
+    }
+
+    fn consume_token(&mut self, source: &str) -> Token {
+        let start = self.position();
+        let (first, second, third) = (self.peek(0), self.peek(1), self.peek(2));
+        macro_rules! single {
+            ($variant:ident) => {{
+                self.consume();
+                Token::$variant(Location {
+                    start,
+                    end: self.position(),
+                })
+            }};
+        }
+        match first {
+            '/' if second == '*' => {
+                self.consume();
+                self.consume();
+                self.consume_comment(source, start)
+            }
+            '/' if second == '/' => {
+                // less inline comment, until the end of line
+                self.consume_while(|c| c != '\n');
+                let end = self.position();
+                Token::Comment(Self::slice(source, &start, &end), Location { start, end })
+            }
+            c if is_whitespace(c) => {
+                self.consume_while(is_whitespace);
+                let end = self.position();
+                Token::Whitespace(Self::slice(source, &start, &end), Location { start, end })
+            }
+            '"' | '\'' => {
+                self.consume();
+                self.consume_string(source, start, first)
+            }
+            '#' if is_ident_char(second) || is_valid_escape(second, third) => {
+                self.consume();
+                let hash_type = if starts_ident(self.peek(0), self.peek(1), self.peek(2)) {
+                    HashType::Id
+                } else {
+                    HashType::Unrestricted
+                };
+                let name_start = self.position();
+                let from = self.index;
+                self.consume_ident_sequence();
+                let name = self.text(source, from, &name_start);
+                let end = self.position();
+                Token::Hash(name, hash_type, Location { start, end })
+            }
+            '(' => single!(OpenParen),
+            ')' => single!(CloseParen),
+            '[' => single!(OpenSquare),
+            ']' => single!(CloseSquare),
+            '{' => single!(OpenCurly),
+            '}' => single!(CloseCurly),
+            ',' => single!(Comma),
+            ':' => single!(Colon),
+            ';' => single!(Semicolon),
+            '+' | '.' if starts_number(first, second, third) => self.consume_numeric(source, start),
+            '-' if starts_number(first, second, third) => self.consume_numeric(source, start),
+            '-' if second == '-' && third == '>' => {
+                self.consume();
+                self.consume();
+                single!(Cdc)
+            }
+            '-' if starts_ident(first, second, third) => self.consume_ident_like(source, start),
+            '<' if second == '!' && third == '-' && self.peek(3) == '-' => {
+                self.consume();
+                self.consume();
+                self.consume();
+                single!(Cdo)
+            }
+            '@' if starts_ident(second, third, self.peek(3)) => {
+                self.consume();
+                let name_start = self.position();
+                let from = self.index;
+                self.consume_ident_sequence();
+                let name = self.text(source, from, &name_start);
+                let end = self.position();
+                Token::AtKeyword(name, Location { start, end })
+            }
+            '@' if second == '{' && self.starts_interpolation() => {
+                self.consume();
+                self.consume();
+                let name_start = self.position();
+                let from = self.index;
+                self.consume_ident_sequence();
+                let name = self.text(source, from, &name_start);
+                self.consume(); // "}"
+                let end = self.position();
+                Token::Interpolation(name, Location { start, end })
+            }
+            '\\' if is_valid_escape(first, second) => self.consume_ident_like(source, start),
+            c if c.is_ascii_digit() => self.consume_numeric(source, start),
+            c if is_ident_start(c) => self.consume_ident_like(source, start),
+            c => {
+                self.consume();
+                Token::Delim(
+                    c,
+                    Location {
+                        start,
+                        end: self.position(),
+                    },
+                )
+            }
+        }
+    }
+
+    /// tokenize the whole source as one buffer
+    pub fn lex(&mut self, source: &str) {
+        self.code_points = preprocess(source);
+        self.source_len = source.len();
+        while !self.is_eof() {
+            let token = self.consume_token(source);
+            self.tokens.push(token);
+        }
     }
 }
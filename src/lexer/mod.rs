@@ -1,16 +1,21 @@
+#[allow(clippy::module_inception)]
 mod lexer;
 
-use async_std::fs::File;
-use async_std::io::{BufReader};
-use async_std::prelude::*;
+use async_std::fs;
 use std::io;
-pub use lexer::{Location, Position, Token, LexerImpl};
+pub use lexer::{HashType, Location, NumberType, Numeric, Position, Text, Token, LexerImpl};
 
 
 pub struct Lexer {
     lexer_impl: LexerImpl,
 }
 
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Lexer {
         Lexer {
@@ -18,26 +23,14 @@ impl Lexer {
         }
     }
 
-    #[allow(dead_code)]
     pub async fn lex_from_path(&mut self, path: &String) -> io::Result<Vec<Token>> {
-        let file = File::open(path).await?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-        while let Some(opt_line) = lines.next().await {
-            match opt_line {
-                Ok(line) => self.lexer_impl.loop_line_for_token(&line),
-                _ => {}
-            }
-        }
+        let source = fs::read_to_string(path).await?;
+        self.lexer_impl.lex(&source);
         Ok(self.lexer_impl.tokens.clone())
     }
 
-    #[allow(dead_code)]
-    pub fn lex_from_source(&mut self, source: &String) -> Vec<Token> {
-        let mut lines = source.lines();
-        while let Some(opt_line) = lines.next() {
-            self.lexer_impl.loop_line_for_token(&opt_line.to_string());
-        }
+    pub fn lex_from_source(&mut self, source: &str) -> Vec<Token> {
+        self.lexer_impl.lex(source);
         self.lexer_impl.tokens.clone()
     }
 }
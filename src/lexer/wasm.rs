@@ -1,11 +1,18 @@
+#[allow(clippy::module_inception)]
 mod lexer;
 
-pub use lexer::{Location, Position, Token, LexerImpl};
+pub use lexer::{HashType, Location, NumberType, Numeric, Position, Text, Token};
 
 pub struct Lexer {
     lexer_impl: lexer::LexerImpl,
 }
 
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Lexer {
         Lexer {
@@ -13,11 +20,8 @@ impl Lexer {
         }
     }
 
-    pub fn lex_from_source(&mut self, source: &String) -> Vec<lexer::Token> {
-        let mut lines = source.lines();
-        while let Some(opt_line) = lines.next() {
-            self.lexer_impl.loop_line_for_token(&opt_line.to_owned());
-        }
+    pub fn lex_from_source(&mut self, source: &str) -> Vec<lexer::Token> {
+        self.lexer_impl.lex(source);
         self.lexer_impl.tokens.clone()
     }
 }
@@ -1,15 +1,154 @@
-#[macro_use]
-extern crate lazy_static;
-#[path = "./lexer/wasm.rs"]
-mod lexer;
-mod parser;
+pub mod compiler;
+pub mod formatter;
+pub mod generator;
+// reading files needs async-std, which is left out of the wasm build
+#[cfg_attr(target_arch = "wasm32", path = "./lexer/wasm.rs")]
+pub mod lexer;
+pub mod minifier;
+pub mod parser;
+#[cfg(test)]
+mod test;
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
+#[allow(deprecated)]
 pub fn parse(source: &str) -> JsValue {
-    let tokens = lexer::Lexer::new().lex_from_source(&source.to_owned());
+    let tokens = lexer::Lexer::new().lex_from_source(source);
     let mut parser = parser::Parser::new(&tokens);
-    let nodes = parser.parse();
-    JsValue::from_serde(&nodes).unwrap()
+    let result = parser.parse();
+    JsValue::from_serde(&result).unwrap()
+}
+
+/// the result of `parse`, or nodes only
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Ast {
+    Result {
+        nodes: Vec<parser::NodeType>,
+        #[serde(default)]
+        raws: Box<parser::Raws>,
+    },
+    Nodes(Vec<parser::NodeType>),
+}
+
+/// generated css with its source map
+#[derive(Serialize)]
+struct Output {
+    css: String,
+    map: Option<generator::SourceMap>,
+}
+
+#[allow(deprecated)]
+fn generate(ast: JsValue, options: JsValue) -> Result<Output, JsValue> {
+    let options: generator::Options = if options.is_undefined() || options.is_null() {
+        generator::Options::default()
+    } else {
+        options
+            .into_serde()
+            .map_err(|error| JsValue::from_str(&error.to_string()))?
+    };
+    let mut generator = generator::Generator::new(&options);
+    let css = match ast.into_serde() {
+        Ok(Ast::Result { nodes, raws }) => generator.generate(&nodes, &raws),
+        Ok(Ast::Nodes(nodes)) => generator.generate(&nodes, &parser::Raws::default()),
+        Err(error) => return Err(JsValue::from_str(&error.to_string())),
+    };
+    Ok(Output {
+        css,
+        map: generator.source_map(),
+    })
+}
+
+#[wasm_bindgen]
+pub fn stringify(ast: JsValue, options: JsValue) -> Result<String, JsValue> {
+    Ok(generate(ast, options)?.css)
+}
+
+/// like `stringify`, return `{ css, map }` with the source map object when `options.map` is set
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn stringify_with_map(ast: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let output = generate(ast, options)?;
+    JsValue::from_serde(&output).map_err(|error| JsValue::from_str(&error.to_string()))
+}
+
+/// parse and minify source, `options` is `{ aggressive: bool }`
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn minify(source: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: minifier::MinifyOptions = if options.is_undefined() || options.is_null() {
+        minifier::MinifyOptions::default()
+    } else {
+        options
+            .into_serde()
+            .map_err(|error| JsValue::from_str(&error.to_string()))?
+    };
+    let tokens = lexer::Lexer::new().lex_from_source(source);
+    let result = parser::Parser::new(&tokens).parse();
+    Ok(minifier::minify(&result.nodes, &options))
+}
+
+/// compiled css with the errors of compiling
+#[derive(Serialize)]
+struct Compiled {
+    css: String,
+    errors: Vec<compiler::CompileError>,
+}
+
+/// parse less source and compile it to css, return `{ css, errors }`
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn compile(source: &str) -> Result<JsValue, JsValue> {
+    let tokens = lexer::Lexer::new().lex_from_source(source);
+    let result = parser::Parser::new(&tokens).parse();
+    let result = compiler::compile(&result.nodes);
+    let css = generator::Generator::new(&generator::Options::default())
+        .generate(&result.nodes, &parser::Raws::default());
+    let compiled = Compiled {
+        css,
+        errors: result.errors,
+    };
+    JsValue::from_serde(&compiled).map_err(|error| JsValue::from_str(&error.to_string()))
+}
+
+/// whether media query list `params` (eg. of "@media") applies to `environment`,
+/// `{ mediaType, width, height, resolution, ... }` with defaults of a desktop screen
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn match_media(params: &str, environment: JsValue) -> Result<bool, JsValue> {
+    let environment: parser::Environment = if environment.is_undefined() || environment.is_null() {
+        parser::Environment::default()
+    } else {
+        environment
+            .into_serde()
+            .map_err(|error| JsValue::from_str(&error.to_string()))?
+    };
+    let tokens = lexer::Lexer::new().lex_from_source(params);
+    let tokens: Vec<&lexer::Token> = tokens.iter().collect();
+    match parser::parse_media_query_list(&tokens) {
+        Some(list) => Ok(parser::matches_media(&list, &environment)),
+        None => Err(JsValue::from_str("invalid media query list")),
+    }
+}
+
+/// whether a browser with `features`, `{ declarations: { prop: [values] }, pseudos, fontTechs, fontFormats }`,
+/// supports `condition` (eg. of "@supports")
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn match_supports(condition: &str, features: JsValue) -> Result<bool, JsValue> {
+    let features: parser::SupportedFeatures = if features.is_undefined() || features.is_null() {
+        parser::SupportedFeatures::default()
+    } else {
+        features
+            .into_serde()
+            .map_err(|error| JsValue::from_str(&error.to_string()))?
+    };
+    let tokens = lexer::Lexer::new().lex_from_source(condition);
+    let tokens: Vec<&lexer::Token> = tokens.iter().collect();
+    match parser::parse_supports_condition(&tokens) {
+        Some(condition) => Ok(condition.matches(&features)),
+        None => Err(JsValue::from_str("invalid supports condition")),
+    }
 }
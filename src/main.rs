@@ -1,19 +1,57 @@
-#[macro_use]
-extern crate lazy_static;
-
-mod lexer;
-mod parser;
-mod test;
-
 use std::io;
 
+use async_std::fs;
 use async_std::task;
+use rust_css_parser_test::{compiler, formatter, generator, lexer, minifier, parser};
 use std::env;
 
-async fn read_file(path: &String) -> io::Result<()> {
+async fn read_file(
+    path: &String,
+    strict: bool,
+    print: bool,
+    minify: bool,
+    compile: bool,
+) -> io::Result<()> {
     let tokens = lexer::Lexer::new().lex_from_path(path).await?;
     let mut parser = parser::Parser::new(&tokens);
-    parser.parse();
+    if strict {
+        if let Err(error) = parser.parse_strict() {
+            eprintln!("{}: {}", path, error);
+        }
+    } else {
+        let result = parser.parse();
+        for error in &result.errors {
+            eprintln!("{}: {}", path, error);
+        }
+        if print {
+            let options = generator::Options::default();
+            let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+            print!("{}", output);
+        }
+        if minify {
+            let options = minifier::MinifyOptions::default();
+            print!("{}", minifier::minify(&result.nodes, &options));
+        }
+        if compile {
+            let options = generator::Options::default();
+            let result = compiler::compile(&result.nodes);
+            for error in &result.errors {
+                eprintln!("{}: {}", path, error);
+            }
+            let output = generator::Generator::new(&options)
+                .generate(&result.nodes, &parser::Raws::default());
+            print!("{}", output);
+        }
+    }
+    Ok(())
+}
+
+async fn format_file(path: &str) -> io::Result<()> {
+    let source = fs::read_to_string(path).await?;
+    match formatter::format(&source, &formatter::FormatOptions::default()) {
+        Ok(output) => print!("{}", output),
+        Err(error) => eprintln!("{}: {}", path, error),
+    }
     Ok(())
 }
 
@@ -23,8 +61,17 @@ fn main() {
         args.push(argument);
     }
     task::block_on(async {
-        if let Some(path) = args.get(1) {
-            let _ = read_file(path).await;
+        let strict = args.iter().any(|arg| arg == "--strict");
+        let print = args.iter().any(|arg| arg == "--print");
+        let minify = args.iter().any(|arg| arg == "--minify");
+        let format = args.iter().any(|arg| arg == "--format");
+        let compile = args.iter().any(|arg| arg == "--compile");
+        if let Some(path) = args.iter().skip(1).find(|arg| !arg.starts_with("--")) {
+            if format {
+                let _ = format_file(path).await;
+            } else {
+                let _ = read_file(path, strict, print, minify, compile).await;
+            }
         }
     });
 }
@@ -0,0 +1,394 @@
+use super::generator::{Generator, Options};
+use super::lexer::{Lexer, Token};
+use super::parser::{NodeType, Raws};
+use serde::{Deserialize, Serialize};
+
+/// units of length, a zero length without unit is still the same length
+const LENGTH_UNITS: [&str; 15] = [
+    "px", "em", "rem", "ex", "ch", "vw", "vh", "vmin", "vmax", "cm", "mm", "q", "in", "pt", "pc",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinifyOptions {
+    /// also drop declarations overridden by a later one of the same prop (vendor fallbacks included),
+    /// `/*! */` comments and empty at-rules
+    pub aggressive: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    Selector,
+    Params,
+    Value,
+}
+
+/// space between tokens which can be dropped
+fn is_optional_space(prev: &Token, next: &Token, part: Part) -> bool {
+    match (prev, next) {
+        (Token::Comma(_), _) | (_, Token::Comma(_)) => true,
+        (Token::OpenParen(_), _) | (Token::Function(_, _), _) | (_, Token::CloseParen(_)) => true,
+        (Token::OpenSquare(_), _) | (_, Token::CloseSquare(_)) => true,
+        (Token::Delim('>', _), _)
+        | (Token::Delim('+', _), _)
+        | (Token::Delim('~', _), _)
+        | (_, Token::Delim('>', _))
+        | (_, Token::Delim('+', _))
+        | (_, Token::Delim('~', _)) => part == Part::Selector,
+        (Token::Colon(_), _) | (_, Token::Colon(_)) => part == Part::Params,
+        _ => false,
+    }
+}
+
+/// "#aabbcc" -> "#abc", "#aabbccdd" -> "#abcd"
+fn shorten_hex(name: &str) -> Option<String> {
+    let bytes = name.as_bytes();
+    if (bytes.len() != 6 && bytes.len() != 8) || !bytes.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let mut short = String::from("#");
+    for pair in bytes.chunks(2) {
+        if !pair[0].eq_ignore_ascii_case(&pair[1]) {
+            return None;
+        }
+        short.push(pair[0] as char);
+    }
+    Some(short)
+}
+
+/// collapse whitespace of a selector, params or value, and shorten hex and zero lengths in value
+fn minify_text(source: &str, part: Part) -> String {
+    let tokens = Lexer::new().lex_from_source(source);
+    let mut output = String::new();
+    let mut prev: Option<&Token> = None;
+    let mut space = false;
+    let mut depth = 0;
+    for token in tokens.iter() {
+        if let Token::Whitespace(_, _) | Token::Comment(_, _) = token {
+            space = prev.is_some();
+            continue;
+        }
+        if let Some(prev) = prev {
+            if space && !is_optional_space(prev, token, part) {
+                output.push(' ');
+            }
+        }
+        space = false;
+        match token {
+            Token::Hash(name, _, _) if part == Part::Value => match shorten_hex(&name.raw) {
+                Some(hex) => output.push_str(&hex),
+                None => output.push_str(&token.raw()),
+            },
+            Token::Dimension(number, unit, _)
+                if part == Part::Value
+                    && depth == 0
+                    && number.value == 0.0
+                    && LENGTH_UNITS.contains(&unit.value.to_ascii_lowercase().as_str()) =>
+            {
+                // "calc(0px + 1em)" needs its unit, only outside functions
+                output.push('0');
+            }
+            _ => output.push_str(&token.raw()),
+        }
+        match token {
+            Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+            Token::CloseParen(_) => depth -= 1,
+            _ => {}
+        }
+        prev = Some(token);
+    }
+    output
+}
+
+/// drop declarations of the same prop overridden by a later one
+///
+/// the safe mode only drops exact duplicates, keep fallbacks like "display: -webkit-box; display: flex"
+fn collapse_decls(nodes: Vec<NodeType>, options: &MinifyOptions) -> Vec<NodeType> {
+    let decl = |node: &NodeType| -> Option<(String, String, bool)> {
+        match node {
+            NodeType::Decl {
+                prop,
+                value,
+                important,
+                ..
+            } => Some((
+                prop.to_ascii_lowercase(),
+                value.to_owned(),
+                important.unwrap_or(false),
+            )),
+            _ => None,
+        }
+    };
+    let overridden = |index: usize| -> bool {
+        let (prop, value, important) = match decl(&nodes[index]) {
+            Some(decl) => decl,
+            None => return false,
+        };
+        let mut others = nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(other, node)| match decl(node) {
+                Some(decl) if other != index && decl.0 == prop => Some((other, decl)),
+                _ => None,
+            });
+        if options.aggressive {
+            // important wins, or the later one
+            others.any(|(other, (_, _, other_important))| {
+                (other_important && !important) || (other > index && other_important == important)
+            })
+        } else {
+            others.any(|(other, (_, other_value, other_important))| {
+                other > index && other_value == value && other_important == important
+            })
+        }
+    };
+    let keep: Vec<bool> = (0..nodes.len()).map(|index| !overridden(index)).collect();
+    nodes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(node, keep)| if keep { Some(node) } else { None })
+        .collect()
+}
+
+fn minify_nodes(nodes: &[NodeType], options: &MinifyOptions) -> Vec<NodeType> {
+    let nodes = nodes
+        .iter()
+        .filter_map(|node| minify_node(node, options))
+        .collect();
+    collapse_decls(nodes, options)
+}
+
+fn block_raws() -> Raws {
+    Raws {
+        before: Some(String::new()),
+        between: Some(String::new()),
+        after: Some(String::new()),
+        semicolon: Some(false),
+        ..Raws::default()
+    }
+}
+
+fn minify_node(node: &NodeType, options: &MinifyOptions) -> Option<NodeType> {
+    match node {
+        NodeType::Root => None,
+        NodeType::Comment { text, inline, .. } => {
+            // keep "/*! license */" in safe mode
+            if *inline || options.aggressive || !text.starts_with('!') {
+                return None;
+            }
+            let mut node = node.to_owned();
+            if let NodeType::Comment { raws, .. } = &mut node {
+                *raws = Raws {
+                    before: Some(String::new()),
+                    left: Some(String::new()),
+                    right: Some(String::new()),
+                    ..Raws::default()
+                };
+            }
+            Some(node)
+        }
+        NodeType::Rule {
+            r#type,
+            selector,
+            selectors,
+            guard,
+            nodes,
+            source,
+            ..
+        } => {
+            let nodes = minify_nodes(nodes, options);
+            if nodes.is_empty() {
+                return None;
+            }
+            Some(NodeType::Rule {
+                r#type: r#type.to_owned(),
+                selector: minify_text(selector, Part::Selector),
+                selectors: selectors.to_owned(),
+                guard: guard.to_owned(),
+                nodes,
+                source: source.to_owned(),
+                raws: block_raws(),
+            })
+        }
+        NodeType::Keyframe {
+            r#type,
+            selector,
+            offsets,
+            ranges,
+            nodes,
+            source,
+            ..
+        } => {
+            let nodes = minify_nodes(nodes, options);
+            if nodes.is_empty() {
+                return None;
+            }
+            Some(NodeType::Keyframe {
+                r#type: r#type.to_owned(),
+                selector: minify_text(selector, Part::Selector),
+                offsets: offsets.to_owned(),
+                ranges: ranges.to_owned(),
+                nodes,
+                source: source.to_owned(),
+                raws: block_raws(),
+            })
+        }
+        NodeType::MixinDefinition {
+            r#type,
+            name,
+            params,
+            defaults,
+            rest,
+            guard,
+            selector,
+            nodes,
+            source,
+            ..
+        } => {
+            let nodes = minify_nodes(nodes, options);
+            if nodes.is_empty() {
+                return None;
+            }
+            Some(NodeType::MixinDefinition {
+                r#type: r#type.to_owned(),
+                name: name.to_owned(),
+                params: params.to_owned(),
+                defaults: defaults.to_owned(),
+                rest: rest.to_owned(),
+                guard: guard.to_owned(),
+                selector: minify_text(selector, Part::Selector),
+                nodes,
+                source: source.to_owned(),
+                raws: block_raws(),
+            })
+        }
+        NodeType::MixinCall {
+            r#type,
+            name,
+            namespace,
+            args,
+            important,
+            selector,
+            source,
+            ..
+        } => Some(NodeType::MixinCall {
+            r#type: r#type.to_owned(),
+            name: name.to_owned(),
+            namespace: namespace.to_owned(),
+            args: args.to_owned(),
+            important: *important,
+            selector: minify_text(selector, Part::Selector),
+            source: source.to_owned(),
+            raws: Raws {
+                before: Some(String::new()),
+                important: Some(String::from("!important")),
+                ..Raws::default()
+            },
+        }),
+        NodeType::Atrule {
+            r#type,
+            name,
+            params,
+            prelude,
+            nodes,
+            source,
+            ..
+        } => {
+            let mut raws = block_raws();
+            let params = minify_text(params, Part::Params);
+            let after_name = if params.is_empty() { "" } else { " " };
+            raws.after_name = Some(String::from(after_name));
+            let nodes = match nodes {
+                Some(nodes) => {
+                    let nodes = minify_nodes(nodes, options);
+                    if nodes.is_empty() && options.aggressive {
+                        return None;
+                    }
+                    Some(nodes)
+                }
+                None => None,
+            };
+            Some(NodeType::Atrule {
+                r#type: r#type.to_owned(),
+                name: name.to_owned(),
+                params,
+                prelude: prelude.to_owned(),
+                nodes,
+                source: source.to_owned(),
+                raws,
+            })
+        }
+        NodeType::VariableDecl {
+            r#type,
+            name,
+            value,
+            values,
+            important,
+            nodes,
+            source,
+            ..
+        } => {
+            let raws = match nodes {
+                Some(_) => block_raws(),
+                None => Raws {
+                    before: Some(String::new()),
+                    between: Some(String::from(":")),
+                    important: Some(String::from("!important")),
+                    ..Raws::default()
+                },
+            };
+            Some(NodeType::VariableDecl {
+                r#type: r#type.to_owned(),
+                name: name.to_owned(),
+                value: minify_text(value, Part::Value),
+                values: values.to_owned(),
+                important: important.to_owned(),
+                // a detached ruleset is kept even if it's empty
+                nodes: nodes.as_ref().map(|nodes| minify_nodes(nodes, options)),
+                source: source.to_owned(),
+                raws,
+            })
+        }
+        NodeType::Decl {
+            r#type,
+            prop,
+            value,
+            values,
+            important,
+            source,
+            ..
+        } => {
+            let value = if prop.starts_with("--") {
+                // custom property values are kept as they are
+                value.to_owned()
+            } else {
+                minify_text(value, Part::Value)
+            };
+            Some(NodeType::Decl {
+                r#type: r#type.to_owned(),
+                prop: prop.to_owned(),
+                value,
+                values: values.to_owned(),
+                important: important.to_owned(),
+                source: source.to_owned(),
+                raws: Raws {
+                    before: Some(String::new()),
+                    between: Some(String::from(":")),
+                    important: Some(String::from("!important")),
+                    ..Raws::default()
+                },
+            })
+        }
+    }
+}
+
+/// print nodes without comments, whitespace and redundant declarations
+pub fn minify(nodes: &[NodeType], options: &MinifyOptions) -> String {
+    let nodes = minify_nodes(nodes, options);
+    let raws = Raws {
+        after: Some(String::new()),
+        semicolon: Some(false),
+        ..Raws::default()
+    };
+    Generator::new(&Options::default()).generate(&nodes, &raws)
+}
@@ -0,0 +1,666 @@
+use super::media::{
+    matching_paren, parse_media_condition, parse_media_query_list, MediaCondition, MediaModifier,
+    MediaQuery, MediaQueryList,
+};
+use super::selector::{parse_selector_list, SelectorList};
+use super::supports::{parse_declaration, parse_supports_condition, SupportsCondition};
+use super::{NodeType, ParseError, ParseErrorKind};
+use crate::lexer::{Location, Token};
+use serde::{Deserialize, Serialize};
+
+/// parsed params of known at-rules
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AtrulePrelude {
+    /// eg. "@media screen and (color)"
+    Media(MediaQueryList),
+    /// eg. "@import url(a.css) layer(base) supports(display: grid) screen"
+    Import {
+        url: String,
+        /// Some("") for an anonymous "layer"
+        layer: Option<String>,
+        /// condition of "supports(...)"
+        supports: Option<SupportsCondition>,
+        media: MediaQueryList,
+    },
+    /// eg. "@supports (display: grid) and (not (display: inline-grid))"
+    Supports(SupportsCondition),
+    /// eg. "@custom-media --narrow (max-width: 30em)", "true" is "all" and "false" is "not all"
+    CustomMedia {
+        name: String,
+        media: MediaQueryList,
+    },
+    /// eg. "@charset \"utf-8\";"
+    Charset(String),
+    /// eg. "@namespace svg url(http://www.w3.org/2000/svg);"
+    Namespace {
+        prefix: Option<String>,
+        url: String,
+    },
+    FontFace,
+    /// eg. "@page :first", "@page toc, index:blank"
+    Page(Vec<PageSelector>),
+    /// margin box of "@page", eg. "top-left" of "@top-left"
+    PageMargin(String),
+    /// eg. "@layer base, components;", "@layer base {}", an anonymous "@layer {}" has no names
+    Layer(Vec<String>),
+    /// eg. "@container sidebar (min-width: 400px)", a "style()" query is `GeneralEnclosed`
+    Container {
+        name: Option<String>,
+        condition: MediaCondition,
+    },
+    /// eg. "@property --angle"
+    Property(String),
+    /// eg. "@counter-style thumbs"
+    CounterStyle(String),
+    /// eg. "@font-feature-values Font One, \"Font Two\""
+    FontFeatureValues(Vec<String>),
+    /// feature type block of "@font-feature-values", eg. "styleset" of "@styleset"
+    FontFeatureType(String),
+    /// eg. "@scope (.card) to (.content)"
+    Scope {
+        root: Option<SelectorList>,
+        limit: Option<SelectorList>,
+    },
+    StartingStyle,
+}
+
+/// eg. "index:blank" of "@page toc, index:blank"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageSelector {
+    pub name: Option<String>,
+    /// lowercase pseudo-classes without ":", eg. "first"
+    pub pseudos: Vec<String>,
+}
+
+const PAGE_MARGINS: [&str; 16] = [
+    "top-left-corner",
+    "top-left",
+    "top-center",
+    "top-right",
+    "top-right-corner",
+    "bottom-left-corner",
+    "bottom-left",
+    "bottom-center",
+    "bottom-right",
+    "bottom-right-corner",
+    "left-top",
+    "left-middle",
+    "left-bottom",
+    "right-top",
+    "right-middle",
+    "right-bottom",
+];
+
+const FONT_FEATURE_TYPES: [&str; 6] = [
+    "stylistic",
+    "historical-forms",
+    "styleset",
+    "character-variant",
+    "swash",
+    "ornaments",
+];
+
+const FONT_FACE_DESCRIPTORS: [&str; 16] = [
+    "font-family",
+    "src",
+    "font-style",
+    "font-weight",
+    "font-stretch",
+    "font-width",
+    "font-display",
+    "unicode-range",
+    "font-feature-settings",
+    "font-variation-settings",
+    "font-named-instance",
+    "font-language-override",
+    "ascent-override",
+    "descent-override",
+    "line-gap-override",
+    "size-adjust",
+];
+
+const COUNTER_STYLE_DESCRIPTORS: [&str; 10] = [
+    "system",
+    "symbols",
+    "additive-symbols",
+    "negative",
+    "prefix",
+    "suffix",
+    "range",
+    "pad",
+    "fallback",
+    "speak-as",
+];
+
+/// tokens without whitespace
+fn significant<'t>(tokens: &[&'t Token]) -> Vec<&'t Token> {
+    tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_, _)))
+        .copied()
+        .collect()
+}
+
+/// tokens without leading and trailing whitespace
+fn trim<'a, 't>(tokens: &'a [&'t Token]) -> &'a [&'t Token] {
+    let whitespace = |token: &&Token| matches!(token, Token::Whitespace(_, _));
+    let from = tokens.iter().position(|token| !whitespace(token));
+    let to = tokens.iter().rposition(|token| !whitespace(token));
+    match (from, to) {
+        (Some(from), Some(to)) => &tokens[from..=to],
+        _ => &[],
+    }
+}
+
+/// split tokens by top level commas
+fn split_commas<'a, 't>(tokens: &'a [&'t Token]) -> Vec<&'a [&'t Token]> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut from = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+            Token::CloseParen(_) => depth -= 1,
+            Token::Comma(_) if depth == 0 => {
+                parts.push(&tokens[from..index]);
+                from = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[from..]);
+    parts
+}
+
+/// source text inside "(...)" of the function at `open`, and the index after ")"
+fn function_text(tokens: &[&Token], open: usize) -> Option<(String, usize)> {
+    let close = matching_paren(tokens, open)?;
+    let text: String = tokens[open + 1..close]
+        .iter()
+        .map(|token| token.raw())
+        .collect();
+    Some((text.trim().to_owned(), close + 1))
+}
+
+fn skip_whitespace(tokens: &[&Token], index: &mut usize) {
+    while let Some(Token::Whitespace(_, _)) = tokens.get(*index) {
+        *index += 1;
+    }
+}
+
+fn parse_import(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let mut index = 0;
+    skip_whitespace(tokens, &mut index);
+    let url = match tokens.get(index)? {
+        Token::String(text, _) | Token::Url(text, _) => {
+            index += 1;
+            text.value.to_owned()
+        }
+        Token::Function(name, _) if name.value.eq_ignore_ascii_case("url") => {
+            let close = matching_paren(tokens, index)?;
+            let url = match tokens[index + 1..close]
+                .iter()
+                .filter(|token| !matches!(token, Token::Whitespace(_, _)))
+                .collect::<Vec<_>>()[..]
+            {
+                [Token::String(text, _)] => text.value.to_owned(),
+                _ => return None,
+            };
+            index = close + 1;
+            url
+        }
+        // eg. less options "(reference)", or a variable
+        _ => return None,
+    };
+    skip_whitespace(tokens, &mut index);
+    let layer = match tokens.get(index) {
+        Some(Token::Ident(ident, _)) if ident.value.eq_ignore_ascii_case("layer") => {
+            index += 1;
+            Some(String::new())
+        }
+        Some(Token::Function(name, _)) if name.value.eq_ignore_ascii_case("layer") => {
+            let (layer, next) = function_text(tokens, index)?;
+            index = next;
+            Some(layer)
+        }
+        _ => None,
+    };
+    skip_whitespace(tokens, &mut index);
+    let supports = match tokens.get(index) {
+        Some(Token::Function(name, _)) if name.value.eq_ignore_ascii_case("supports") => {
+            let close = matching_paren(tokens, index)?;
+            let inner = &tokens[index + 1..close];
+            let supports = parse_supports_condition(inner).or_else(|| parse_declaration(inner))?;
+            index = close + 1;
+            Some(supports)
+        }
+        _ => None,
+    };
+    Some(AtrulePrelude::Import {
+        url,
+        layer,
+        supports,
+        media: parse_media_query_list(&tokens[index..])?,
+    })
+}
+
+fn parse_custom_media(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let mut index = 0;
+    skip_whitespace(tokens, &mut index);
+    let name = match tokens.get(index)? {
+        Token::Ident(ident, _) if ident.value.starts_with("--") => ident.value.to_owned(),
+        _ => return None,
+    };
+    index += 1;
+    let rest: Vec<&Token> = tokens[index..]
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_, _)))
+        .copied()
+        .collect();
+    let keyword = match rest[..] {
+        [Token::Ident(ident, _)] => ident.value.to_ascii_lowercase(),
+        _ => String::new(),
+    };
+    let all = |modifier| {
+        vec![MediaQuery {
+            modifier,
+            media_type: Some(String::from("all")),
+            condition: None,
+        }]
+    };
+    let media = match keyword.as_str() {
+        "true" => all(None),
+        "false" => all(Some(MediaModifier::Not)),
+        _ => parse_media_query_list(&tokens[index..])?,
+    };
+    Some(AtrulePrelude::CustomMedia { name, media })
+}
+
+fn parse_namespace(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let tokens = significant(tokens);
+    let (prefix, url) = match tokens[..] {
+        [Token::Ident(prefix, _), url] => (Some(prefix.value.to_owned()), url),
+        [url] => (None, url),
+        _ => return None,
+    };
+    let url = match url {
+        Token::String(text, _) | Token::Url(text, _) => text.value.to_owned(),
+        _ => return None,
+    };
+    Some(AtrulePrelude::Namespace { prefix, url })
+}
+
+fn parse_page(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let tokens = significant(tokens);
+    if tokens.is_empty() {
+        return Some(AtrulePrelude::Page(vec![]));
+    }
+    let mut selectors = vec![];
+    for part in split_commas(&tokens) {
+        let (name, mut rest) = match part {
+            [Token::Ident(name, _), rest @ ..] => (Some(name.value.to_owned()), rest),
+            rest => (None, rest),
+        };
+        let mut pseudos = vec![];
+        while let [Token::Colon(_), Token::Ident(pseudo, _), next @ ..] = rest {
+            pseudos.push(pseudo.value.to_ascii_lowercase());
+            rest = next;
+        }
+        if !rest.is_empty() || (name.is_none() && pseudos.is_empty()) {
+            return None;
+        }
+        selectors.push(PageSelector { name, pseudos });
+    }
+    Some(AtrulePrelude::Page(selectors))
+}
+
+fn parse_layer(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .skip_while(|token| matches!(token, Token::Whitespace(_, _)))
+        .copied()
+        .collect();
+    if tokens
+        .iter()
+        .all(|token| matches!(token, Token::Whitespace(_, _)))
+    {
+        return Some(AtrulePrelude::Layer(vec![]));
+    }
+    let mut names = vec![];
+    for part in split_commas(&tokens) {
+        // no whitespace inside a name, eg. "base.theme"
+        let part = trim(part);
+        let mut name = String::new();
+        for (index, token) in part.iter().enumerate() {
+            match token {
+                Token::Ident(ident, _) if index % 2 == 0 => name.push_str(&ident.value),
+                Token::Delim('.', _) if index % 2 == 1 => name.push('.'),
+                _ => return None,
+            }
+        }
+        if name.is_empty() || name.ends_with('.') {
+            return None;
+        }
+        names.push(name);
+    }
+    Some(AtrulePrelude::Layer(names))
+}
+
+fn parse_container(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let mut index = 0;
+    skip_whitespace(tokens, &mut index);
+    let name = match tokens.get(index)? {
+        Token::Ident(ident, _)
+            if !["not", "and", "or", "none"]
+                .contains(&ident.value.to_ascii_lowercase().as_str()) =>
+        {
+            index += 1;
+            Some(ident.value.to_owned())
+        }
+        _ => None,
+    };
+    let condition = parse_media_condition(&tokens[index..])?;
+    Some(AtrulePrelude::Container { name, condition })
+}
+
+/// a single ident, which is not a CSS-wide keyword
+fn parse_name(tokens: &[&Token]) -> Option<String> {
+    match significant(tokens)[..] {
+        [Token::Ident(ident, _)]
+            if ![
+                "initial",
+                "inherit",
+                "unset",
+                "revert",
+                "revert-layer",
+                "default",
+            ]
+            .contains(&ident.value.to_ascii_lowercase().as_str()) =>
+        {
+            Some(ident.value.to_owned())
+        }
+        _ => None,
+    }
+}
+
+fn parse_font_families(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let tokens = significant(tokens);
+    let mut families = vec![];
+    for part in split_commas(&tokens) {
+        let family = match part {
+            [Token::String(text, _)] => text.value.to_owned(),
+            [] => return None,
+            idents => {
+                let mut names = vec![];
+                for token in idents {
+                    match token {
+                        Token::Ident(ident, _) => names.push(ident.value.to_owned()),
+                        _ => return None,
+                    }
+                }
+                names.join(" ")
+            }
+        };
+        families.push(family);
+    }
+    Some(AtrulePrelude::FontFeatureValues(families))
+}
+
+fn parse_scope(tokens: &[&Token]) -> Option<AtrulePrelude> {
+    let mut index = 0;
+    // selector list inside "(...)" at index
+    let scope_selector = |index: &mut usize| -> Option<SelectorList> {
+        let close = matching_paren(tokens, *index)?;
+        let inner: Vec<Token> = tokens[*index + 1..close]
+            .iter()
+            .map(|token| (*token).to_owned())
+            .collect();
+        *index = close + 1;
+        parse_selector_list(&inner)
+    };
+    skip_whitespace(tokens, &mut index);
+    let root = match tokens.get(index) {
+        Some(Token::OpenParen(_)) => Some(scope_selector(&mut index)?),
+        _ => None,
+    };
+    skip_whitespace(tokens, &mut index);
+    let limit = match tokens.get(index) {
+        Some(Token::Ident(ident, _)) if ident.value.eq_ignore_ascii_case("to") => {
+            index += 1;
+            skip_whitespace(tokens, &mut index);
+            match tokens.get(index) {
+                Some(Token::OpenParen(_)) => Some(scope_selector(&mut index)?),
+                _ => return None,
+            }
+        }
+        _ => None,
+    };
+    skip_whitespace(tokens, &mut index);
+    if index < tokens.len() {
+        return None;
+    }
+    Some(AtrulePrelude::Scope { root, limit })
+}
+
+/// the prelude of an at-rule without params
+fn empty(tokens: &[&Token], prelude: AtrulePrelude) -> Option<AtrulePrelude> {
+    if significant(tokens).is_empty() {
+        Some(prelude)
+    } else {
+        None
+    }
+}
+
+/// parse the params of an at-rule from its tokens, None for unknown at-rules or invalid params
+pub fn parse_prelude(name: &str, tokens: &[Token]) -> Option<AtrulePrelude> {
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Comment(_, _)))
+        .collect();
+    let name = name.to_ascii_lowercase();
+    match name.as_str() {
+        "media" => Some(AtrulePrelude::Media(parse_media_query_list(&tokens)?)),
+        "import" => parse_import(&tokens),
+        "supports" => Some(AtrulePrelude::Supports(parse_supports_condition(&tokens)?)),
+        "custom-media" => parse_custom_media(&tokens),
+        "charset" => match significant(&tokens)[..] {
+            [Token::String(text, _)] => Some(AtrulePrelude::Charset(text.value.to_owned())),
+            _ => None,
+        },
+        "namespace" => parse_namespace(&tokens),
+        "font-face" => empty(&tokens, AtrulePrelude::FontFace),
+        "page" => parse_page(&tokens),
+        "layer" => parse_layer(&tokens),
+        "container" => parse_container(&tokens),
+        "property" => match parse_name(&tokens) {
+            Some(name) if name.starts_with("--") => Some(AtrulePrelude::Property(name)),
+            _ => None,
+        },
+        "counter-style" => match parse_name(&tokens) {
+            Some(name) if !name.eq_ignore_ascii_case("none") => {
+                Some(AtrulePrelude::CounterStyle(name))
+            }
+            _ => None,
+        },
+        "font-feature-values" => parse_font_families(&tokens),
+        "scope" => parse_scope(&tokens),
+        "starting-style" => empty(&tokens, AtrulePrelude::StartingStyle),
+        name if PAGE_MARGINS.contains(&name) => {
+            empty(&tokens, AtrulePrelude::PageMargin(name.to_owned()))
+        }
+        name if FONT_FEATURE_TYPES.contains(&name) => {
+            empty(&tokens, AtrulePrelude::FontFeatureType(name.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+/// an error of an at-rule or its descriptors
+fn invalid(kind: ParseErrorKind, message: String, span: &Location) -> ParseError {
+    ParseError {
+        kind,
+        message,
+        span: span.to_owned(),
+    }
+}
+
+/// declarations of a block with lowercase props
+fn descriptors(nodes: &[NodeType]) -> Vec<(String, &str, &Location)> {
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            NodeType::Decl {
+                prop,
+                value,
+                source,
+                ..
+            } => Some((prop.to_ascii_lowercase(), value.as_str(), source)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// less variables, interpolation, escapes and vendor prefixes are not checked,
+/// eg. "font-display: @display" or "-moz-font-feature-settings"
+fn unchecked(text: &str) -> bool {
+    text.contains('@') || text.contains('~') || text.starts_with('-')
+}
+
+/// validate params, block and descriptors of the standard at-rules,
+/// other at-rules, and "@media", "@import" and "@supports" which may hold less variables, are not checked
+pub fn validate_atrule(
+    name: &str,
+    prelude: &Option<AtrulePrelude>,
+    nodes: &Option<Vec<NodeType>>,
+    source: &Location,
+) -> Vec<ParseError> {
+    let name = name.to_ascii_lowercase();
+    let mut errors = vec![];
+    let statement = ["charset", "namespace"].contains(&name.as_str());
+    let block = [
+        "font-face",
+        "page",
+        "container",
+        "property",
+        "counter-style",
+        "font-feature-values",
+        "scope",
+        "starting-style",
+    ]
+    .contains(&name.as_str())
+        || PAGE_MARGINS.contains(&name.as_str())
+        || FONT_FEATURE_TYPES.contains(&name.as_str());
+    if !statement && !block && name != "layer" {
+        return errors;
+    }
+    let prelude = match prelude {
+        Some(prelude) => prelude,
+        None => {
+            errors.push(invalid(
+                ParseErrorKind::InvalidAtrule,
+                format!("invalid params of \"@{}\"", name),
+                source,
+            ));
+            return errors;
+        }
+    };
+    let nodes = match (nodes, prelude) {
+        (Some(_), _) if statement => {
+            errors.push(invalid(
+                ParseErrorKind::InvalidAtrule,
+                format!("\"@{}\" can't have a block", name),
+                source,
+            ));
+            return errors;
+        }
+        (Some(nodes), AtrulePrelude::Layer(names)) if names.len() > 1 => {
+            errors.push(invalid(
+                ParseErrorKind::InvalidAtrule,
+                String::from("\"@layer\" with a block takes one name at most"),
+                source,
+            ));
+            nodes
+        }
+        (Some(nodes), _) => nodes,
+        (None, AtrulePrelude::Layer(names)) if names.is_empty() => {
+            errors.push(invalid(
+                ParseErrorKind::InvalidAtrule,
+                String::from("\"@layer\" without a block takes one name at least"),
+                source,
+            ));
+            return errors;
+        }
+        (None, _) if block => {
+            errors.push(invalid(
+                ParseErrorKind::InvalidAtrule,
+                format!("expected a block after \"@{}\"", name),
+                source,
+            ));
+            return errors;
+        }
+        (None, _) => return errors,
+    };
+    let allowed: &[&str] = match prelude {
+        AtrulePrelude::FontFace => &FONT_FACE_DESCRIPTORS,
+        AtrulePrelude::Property(_) => &["syntax", "inherits", "initial-value"],
+        AtrulePrelude::CounterStyle(_) => &COUNTER_STYLE_DESCRIPTORS,
+        AtrulePrelude::FontFeatureValues(_) => &["font-display"],
+        // any name of a feature value
+        AtrulePrelude::FontFeatureType(_) => &[],
+        _ => return errors,
+    };
+    let descriptors = descriptors(nodes);
+    for (prop, value, source) in descriptors.iter() {
+        if unchecked(prop) {
+            continue;
+        }
+        let valid_value = match (prelude, prop.as_str()) {
+            _ if unchecked(value) => true,
+            (AtrulePrelude::FontFeatureType(_), _) => value
+                .split_whitespace()
+                .all(|index| index.bytes().all(|b| b.is_ascii_digit())),
+            (_, "inherits") => ["true", "false"].contains(&value.to_ascii_lowercase().as_str()),
+            (_, "syntax") => value.starts_with(['"', '\'']),
+            (_, "font-display") => ["auto", "block", "swap", "fallback", "optional"]
+                .contains(&value.to_ascii_lowercase().as_str()),
+            _ => true,
+        };
+        if !allowed.is_empty() && !allowed.contains(&prop.as_str()) {
+            errors.push(invalid(
+                ParseErrorKind::InvalidDescriptor,
+                format!("unknown descriptor \"{}\" in \"@{}\"", prop, name),
+                source,
+            ));
+        } else if !valid_value {
+            errors.push(invalid(
+                ParseErrorKind::InvalidDescriptor,
+                format!("invalid value of \"{}\" in \"@{}\"", prop, name),
+                source,
+            ));
+        }
+    }
+    let has = |descriptor: &str| descriptors.iter().any(|(prop, _, _)| prop == descriptor);
+    let required: &[&str] = match prelude {
+        AtrulePrelude::FontFace => &["font-family", "src"],
+        AtrulePrelude::Property(_) => {
+            // a universal syntax doesn't need an initial value
+            let universal = descriptors.iter().any(|(prop, value, _)| {
+                prop == "syntax" && value.trim_matches(['"', '\'']).trim() == "*"
+            });
+            if universal {
+                &["syntax", "inherits"]
+            } else {
+                &["syntax", "inherits", "initial-value"]
+            }
+        }
+        _ => &[],
+    };
+    for descriptor in required.iter().filter(|descriptor| !has(descriptor)) {
+        errors.push(invalid(
+            ParseErrorKind::MissingDescriptor,
+            format!("expected \"{}\" descriptor in \"@{}\"", descriptor, name),
+            source,
+        ));
+    }
+    errors
+}
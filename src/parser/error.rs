@@ -0,0 +1,56 @@
+use crate::lexer::Location;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParseErrorKind {
+    /// a token which can not start or continue the current construct
+    UnexpectedToken,
+    /// input ended in the middle of a rule, declaration or at-rule
+    UnexpectedEof,
+    /// a `{` block without its closing `}`
+    UnclosedBlock,
+    /// a `(` without its closing `)`
+    UnclosedParen,
+    /// a declaration without `:` between prop and value
+    MissingColon,
+    /// a declaration without prop before `:`
+    MissingProp,
+    /// "@" not followed by a name
+    MissingAtruleName,
+    /// statement at-rule such as `@import;` without params
+    EmptyAtruleParams,
+    /// a standard at-rule with invalid params, or with a block where it takes none or the reverse
+    InvalidAtrule,
+    /// a descriptor unknown to the at-rule, or with an invalid value
+    InvalidDescriptor,
+    /// a required descriptor is missing, eg. "src" of "@font-face"
+    MissingDescriptor,
+    /// a rule in "@keyframes" with a selector other than "from", "to" or percentages
+    InvalidKeyframeSelector,
+    /// "!important" in a keyframe, which is ignored
+    ImportantInKeyframe,
+    /// a less guard after "when" which is not a valid condition, eg. "when @a > 1"
+    InvalidGuard,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    pub span: Location,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}",
+            self.message,
+            self.span.start.line + 1,
+            self.span.start.column + 1
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
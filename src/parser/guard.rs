@@ -0,0 +1,224 @@
+use super::media::{matching_paren, RangeOperator};
+use crate::lexer::Token;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// condition of a less guard after "when", eg. "(iscolor(@a)) and (@a > 10)"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Guard {
+    Not(Box<Guard>),
+    And(Vec<Guard>),
+    /// conditions separated by "," or "or"
+    Or(Vec<Guard>),
+    /// eg. "(@a > 10)", the operands as source text, "=<" is "<="
+    Compare {
+        left: String,
+        operator: RangeOperator,
+        right: String,
+    },
+    /// a single operand as source text, eg. "iscolor(@a)", "@debug" or "default()"
+    Value(String),
+}
+
+/// evaluate the operands of a guard, eg. with the variables of a less compilation
+pub trait GuardEvaluator {
+    /// whether a single operand is true, eg. "default()" for the default mixin
+    fn truthy(&mut self, operand: &str) -> bool;
+    /// order of two operands, None if they can't be compared, eg. "1px" and "red"
+    fn compare(&mut self, left: &str, right: &str) -> Option<Ordering>;
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Ident(ident, _)) if ident.value.eq_ignore_ascii_case(keyword))
+}
+
+fn text_of(tokens: &[&Token]) -> String {
+    let text: String = tokens.iter().map(|token| token.raw()).collect();
+    text.trim().to_owned()
+}
+
+struct GuardParser<'t> {
+    /// tokens without comments
+    tokens: Vec<&'t Token>,
+    index: usize,
+}
+
+impl<'t> GuardParser<'t> {
+    fn new(tokens: &[&'t Token]) -> GuardParser<'t> {
+        GuardParser {
+            tokens: tokens
+                .iter()
+                .filter(|token| !matches!(token, Token::Comment(_, _)))
+                .copied()
+                .collect(),
+            index: 0,
+        }
+    }
+
+    /// the next token after whitespace
+    fn peek(&mut self) -> Option<&'t Token> {
+        while let Some(Token::Whitespace(_, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
+        self.tokens.get(self.index).copied()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn parse_or(&mut self) -> Option<Guard> {
+        let mut guards = vec![self.parse_and()?];
+        loop {
+            match self.peek() {
+                Some(Token::Comma(_)) => self.index += 1,
+                token if is_keyword(token, "or") => self.index += 1,
+                _ => break,
+            }
+            guards.push(self.parse_and()?);
+        }
+        match guards.len() {
+            1 => guards.pop(),
+            _ => Some(Guard::Or(guards)),
+        }
+    }
+
+    fn parse_and(&mut self) -> Option<Guard> {
+        let mut guards = vec![self.parse_not()?];
+        while is_keyword(self.peek(), "and") {
+            self.index += 1;
+            guards.push(self.parse_not()?);
+        }
+        match guards.len() {
+            1 => guards.pop(),
+            _ => Some(Guard::And(guards)),
+        }
+    }
+
+    fn parse_not(&mut self) -> Option<Guard> {
+        if is_keyword(self.peek(), "not") {
+            self.index += 1;
+            return Some(Guard::Not(Box::new(self.parse_not()?)));
+        }
+        match self.peek()? {
+            Token::OpenParen(_) => {}
+            _ => return None,
+        }
+        let start = self.index;
+        let close = matching_paren(&self.tokens, start)?;
+        self.index = close + 1;
+        let inner = &self.tokens[start + 1..close];
+        // nested condition, eg. "((@a) or (@b))"
+        let mut parser = GuardParser::new(inner);
+        match parser.parse_or() {
+            Some(guard) if parser.at_end() => Some(guard),
+            _ => parse_comparison(inner),
+        }
+    }
+}
+
+/// "@a > 10" or a single operand inside parens
+fn parse_comparison(tokens: &[&Token]) -> Option<Guard> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        let operator = match token {
+            Token::OpenParen(_) | Token::Function(_, _) | Token::OpenSquare(_) => {
+                depth += 1;
+                continue;
+            }
+            Token::CloseParen(_) | Token::CloseSquare(_) => {
+                depth -= 1;
+                continue;
+            }
+            _ if depth > 0 => continue,
+            Token::Delim('<', _) => RangeOperator::Lt,
+            Token::Delim('>', _) => RangeOperator::Gt,
+            Token::Delim('=', _) => RangeOperator::Eq,
+            _ => continue,
+        };
+        let (operator, len) = match (operator, tokens.get(index + 1)) {
+            (RangeOperator::Lt, Some(Token::Delim('=', _))) => (RangeOperator::Le, 2),
+            (RangeOperator::Gt, Some(Token::Delim('=', _))) => (RangeOperator::Ge, 2),
+            (RangeOperator::Eq, Some(Token::Delim('<', _))) => (RangeOperator::Le, 2),
+            (operator, _) => (operator, 1),
+        };
+        let left = text_of(&tokens[..index]);
+        let right = text_of(&tokens[index + len..]);
+        if left.is_empty() || right.is_empty() {
+            return None;
+        }
+        return Some(Guard::Compare {
+            left,
+            operator,
+            right,
+        });
+    }
+    let value = text_of(tokens);
+    if value.is_empty() {
+        return None;
+    }
+    Some(Guard::Value(value))
+}
+
+/// index of "when" starting a guard, eg. of ".m() when (@a)" or "& when (@a)"
+pub fn search_when(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen(_)
+            | Token::Function(_, _)
+            | Token::OpenSquare(_)
+            | Token::OpenCurly(_) => depth += 1,
+            Token::CloseParen(_) | Token::CloseSquare(_) | Token::CloseCurly(_) => depth -= 1,
+            // "when" is a separate word, not eg. the class ".when"
+            Token::Ident(ident, _)
+                if depth == 0
+                    && ident.value == "when"
+                    && index > 0
+                    && matches!(tokens[index - 1], Token::Whitespace(_, _)) =>
+            {
+                return Some(index)
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// parse the condition of a less guard after "when", None if it's not valid
+pub fn parse_guard(tokens: &[Token]) -> Option<Guard> {
+    let tokens: Vec<&Token> = tokens.iter().collect();
+    let mut parser = GuardParser::new(&tokens);
+    let guard = parser.parse_or()?;
+    if parser.at_end() {
+        Some(guard)
+    } else {
+        None
+    }
+}
+
+impl Guard {
+    /// whether the guard passes, with the operands evaluated by `evaluator`
+    pub fn evaluate(&self, evaluator: &mut impl GuardEvaluator) -> bool {
+        match self {
+            Guard::Not(guard) => !guard.evaluate(evaluator),
+            Guard::And(guards) => guards.iter().all(|guard| guard.evaluate(evaluator)),
+            Guard::Or(guards) => guards.iter().any(|guard| guard.evaluate(evaluator)),
+            Guard::Compare {
+                left,
+                operator,
+                right,
+            } => match evaluator.compare(left, right) {
+                Some(ordering) => match operator {
+                    RangeOperator::Lt => ordering == Ordering::Less,
+                    RangeOperator::Le => ordering != Ordering::Greater,
+                    RangeOperator::Gt => ordering == Ordering::Greater,
+                    RangeOperator::Ge => ordering != Ordering::Less,
+                    RangeOperator::Eq => ordering == Ordering::Equal,
+                },
+                None => false,
+            },
+            Guard::Value(operand) => evaluator.truthy(operand),
+        }
+    }
+}
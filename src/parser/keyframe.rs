@@ -0,0 +1,53 @@
+use crate::lexer::{Lexer, Token};
+
+const TIMELINE_RANGES: [&str; 6] = [
+    "cover",
+    "contain",
+    "entry",
+    "exit",
+    "entry-crossing",
+    "exit-crossing",
+];
+
+/// "@keyframes" with vendor prefixes, eg. "@-webkit-keyframes"
+pub fn is_keyframes(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "keyframes" || (name.starts_with('-') && name.ends_with("-keyframes"))
+}
+
+/// parse a keyframe selector list, eg. "from, 50%, entry 10%",
+/// into offsets from 0 to 1 with their timeline range names, None if it's not valid
+pub fn parse_keyframe_selector(selector: &str) -> Option<Vec<(f32, Option<String>)>> {
+    let tokens = Lexer::new().lex_from_source(selector);
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_, _) | Token::Comment(_, _)))
+        .collect();
+    let mut offsets = vec![];
+    for part in tokens.split(|token| matches!(token, Token::Comma(_))) {
+        let percentage = |number: f64| {
+            if (0.0..=100.0).contains(&number) {
+                Some((number / 100.0) as f32)
+            } else {
+                None
+            }
+        };
+        let offset = match part {
+            [Token::Ident(ident, _)] if ident.value.eq_ignore_ascii_case("from") => (0.0, None),
+            [Token::Ident(ident, _)] if ident.value.eq_ignore_ascii_case("to") => (1.0, None),
+            [Token::Percentage(number, _)] => (percentage(number.value)?, None),
+            // timeline range, eg. "entry 10%"
+            [Token::Ident(range, _), Token::Percentage(number, _)]
+                if TIMELINE_RANGES.contains(&range.value.to_ascii_lowercase().as_str()) =>
+            {
+                (
+                    percentage(number.value)?,
+                    Some(range.value.to_ascii_lowercase()),
+                )
+            }
+            _ => return None,
+        };
+        offsets.push(offset);
+    }
+    Some(offsets)
+}
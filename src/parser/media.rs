@@ -0,0 +1,653 @@
+use crate::lexer::Token;
+use serde::{Deserialize, Serialize};
+
+/// media queries level 4, <https://www.w3.org/TR/mediaqueries-4/>
+pub type MediaQueryList = Vec<MediaQuery>;
+
+/// eg. "only screen and (max-width: 1000px)"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaQuery {
+    pub modifier: Option<MediaModifier>,
+    /// lowercase media type, None for a query of a condition only, eg. "(color)"
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaModifier {
+    Not,
+    Only,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    /// `<general-enclosed>` as source text, unknown now but maybe valid in the future,
+    /// eg. "(hover) or foo(bar)"
+    GeneralEnclosed(String),
+    /// less variable of a media query, eg. "phone" of "@media @phone" or "@media @{phone}",
+    /// unknown until it's replaced
+    Variable(String),
+}
+
+/// feature names are lowercase
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaFeature {
+    /// eg. "(color)"
+    Boolean { name: String },
+    /// eg. "(max-width: 1000px)"
+    Plain { name: String, value: MediaValue },
+    /// eg. "(width >= 600px)", "(600px <= width)" is turned to "(width >= 600px)"
+    Range {
+        name: String,
+        operator: RangeOperator,
+        value: MediaValue,
+    },
+    /// eg. "(400px <= width < 700px)", in source order
+    Interval {
+        name: String,
+        start: MediaValue,
+        start_operator: RangeOperator,
+        end_operator: RangeOperator,
+        end: MediaValue,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaValue {
+    Number(f64),
+    Dimension {
+        value: f64,
+        unit: String,
+    },
+    /// eg. "16/9"
+    Ratio(f64, f64),
+    Ident(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RangeOperator {
+    /// "<"
+    Lt,
+    /// "<="
+    Le,
+    /// ">"
+    Gt,
+    /// ">="
+    Ge,
+    /// "="
+    Eq,
+}
+
+impl RangeOperator {
+    /// the operator with sides swapped, eg. "<" for "a > b" as "b < a"
+    pub fn flip(self) -> RangeOperator {
+        match self {
+            RangeOperator::Lt => RangeOperator::Gt,
+            RangeOperator::Le => RangeOperator::Ge,
+            RangeOperator::Gt => RangeOperator::Lt,
+            RangeOperator::Ge => RangeOperator::Le,
+            RangeOperator::Eq => RangeOperator::Eq,
+        }
+    }
+
+    fn is_less(self) -> bool {
+        matches!(self, RangeOperator::Lt | RangeOperator::Le)
+    }
+
+    fn is_greater(self) -> bool {
+        matches!(self, RangeOperator::Gt | RangeOperator::Ge)
+    }
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Ident(ident, _)) if ident.value.eq_ignore_ascii_case(keyword))
+}
+
+/// index of the ")" closing the "(" or function at `open`
+pub(super) fn matching_paren(tokens: &[&Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+            Token::CloseParen(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+struct MediaParser<'t> {
+    /// tokens without comments
+    tokens: Vec<&'t Token>,
+    index: usize,
+}
+
+impl<'t> MediaParser<'t> {
+    fn new(tokens: &[&'t Token]) -> MediaParser<'t> {
+        MediaParser {
+            tokens: tokens
+                .iter()
+                .filter(|token| !matches!(token, Token::Comment(_, _)))
+                .copied()
+                .collect(),
+            index: 0,
+        }
+    }
+
+    /// the next token after whitespace
+    fn peek(&mut self) -> Option<&'t Token> {
+        while let Some(Token::Whitespace(_, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
+        self.tokens.get(self.index).copied()
+    }
+
+    fn eat(&mut self, len: usize) {
+        self.index += len;
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn parse_query(&mut self) -> Option<MediaQuery> {
+        let modifier = match self.peek() {
+            Some(Token::Ident(ident, _)) => match ident.value.to_ascii_lowercase().as_str() {
+                "not" => {
+                    // "not (color)" is a condition
+                    let saved = self.index;
+                    self.eat(1);
+                    let typed = matches!(self.peek(), Some(Token::Ident(_, _)));
+                    self.index = saved;
+                    if typed {
+                        Some(MediaModifier::Not)
+                    } else {
+                        None
+                    }
+                }
+                "only" => Some(MediaModifier::Only),
+                _ => None,
+            },
+            _ => None,
+        };
+        if modifier.is_some() {
+            self.eat(1);
+        }
+        let media_type = match self.peek() {
+            Some(Token::Ident(ident, _)) => {
+                let media_type = ident.value.to_ascii_lowercase();
+                if ["not", "only", "and", "or", "layer"].contains(&media_type.as_str()) {
+                    None
+                } else {
+                    Some(media_type)
+                }
+            }
+            _ => None,
+        };
+        let condition = match media_type {
+            Some(_) => {
+                self.eat(1);
+                if self.at_end() {
+                    None
+                } else if is_keyword(self.peek(), "and") {
+                    self.eat(1);
+                    Some(self.parse_condition(false)?)
+                } else {
+                    return None;
+                }
+            }
+            None if modifier.is_some() => return None,
+            None => Some(self.parse_condition(true)?),
+        };
+        if !self.at_end() {
+            return None;
+        }
+        Some(MediaQuery {
+            modifier,
+            media_type,
+            condition,
+        })
+    }
+
+    fn parse_condition(&mut self, allow_or: bool) -> Option<MediaCondition> {
+        if is_keyword(self.peek(), "not") {
+            self.eat(1);
+            return Some(MediaCondition::Not(Box::new(self.parse_in_parens()?)));
+        }
+        let mut conditions = vec![self.parse_in_parens()?];
+        let mut keyword: Option<String> = None;
+        while let Some(Token::Ident(ident, _)) = self.peek() {
+            let next = ident.value.to_ascii_lowercase();
+            match &keyword {
+                // "and" and "or" can't be mixed without parens
+                Some(keyword) if *keyword != next => return None,
+                _ if next == "and" || (next == "or" && allow_or) => {}
+                _ => return None,
+            }
+            self.eat(1);
+            conditions.push(self.parse_in_parens()?);
+            keyword = Some(next);
+        }
+        match keyword.as_deref() {
+            Some("and") => Some(MediaCondition::And(conditions)),
+            Some(_) => Some(MediaCondition::Or(conditions)),
+            None => conditions.pop(),
+        }
+    }
+
+    fn parse_in_parens(&mut self) -> Option<MediaCondition> {
+        match self.peek()? {
+            Token::OpenParen(_) | Token::Function(_, _) => {}
+            Token::AtKeyword(name, _) | Token::Interpolation(name, _) => {
+                self.eat(1);
+                return Some(MediaCondition::Variable(name.value.to_owned()));
+            }
+            _ => return None,
+        };
+        let start = self.index;
+        let close = matching_paren(&self.tokens, start)?;
+        self.index = close + 1;
+        let raw: String = self.tokens[start..=close]
+            .iter()
+            .map(|token| token.raw())
+            .collect();
+        let general = MediaCondition::GeneralEnclosed(raw.trim().to_owned());
+        if let Token::Function(_, _) = self.tokens[start] {
+            return Some(general);
+        }
+        let inner = &self.tokens[start + 1..close];
+        let mut parser = MediaParser::new(inner);
+        if let Some(condition) = parser.parse_condition(true) {
+            if parser.at_end() {
+                return Some(condition);
+            }
+        }
+        let mut parser = MediaParser::new(inner);
+        match parser.parse_feature() {
+            Some(feature) if parser.at_end() => Some(MediaCondition::Feature(feature)),
+            _ => Some(general),
+        }
+    }
+
+    fn parse_feature(&mut self) -> Option<MediaFeature> {
+        if let Some(Token::Ident(ident, _)) = self.peek() {
+            let name = ident.value.to_ascii_lowercase();
+            let saved = self.index;
+            self.eat(1);
+            match self.peek() {
+                None => return Some(MediaFeature::Boolean { name }),
+                Some(Token::Colon(_)) => {
+                    self.eat(1);
+                    let value = self.parse_value()?;
+                    return Some(MediaFeature::Plain { name, value });
+                }
+                _ => self.index = saved,
+            }
+        }
+        let first = self.parse_value()?;
+        let operator = self.parse_operator()?;
+        let second = self.parse_value()?;
+        if self.at_end() {
+            return match (first, second) {
+                (MediaValue::Ident(name), value) => Some(MediaFeature::Range {
+                    name,
+                    operator,
+                    value,
+                }),
+                (value, MediaValue::Ident(name)) => Some(MediaFeature::Range {
+                    name,
+                    operator: operator.flip(),
+                    value,
+                }),
+                _ => None,
+            };
+        }
+        let end_operator = self.parse_operator()?;
+        let end = self.parse_value()?;
+        let same_direction = (operator.is_less() && end_operator.is_less())
+            || (operator.is_greater() && end_operator.is_greater());
+        match second {
+            MediaValue::Ident(name) if same_direction => Some(MediaFeature::Interval {
+                name,
+                start: first,
+                start_operator: operator,
+                end_operator,
+                end,
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<MediaValue> {
+        let value = match self.peek()? {
+            Token::Number(number, _) => {
+                self.eat(1);
+                if let Some(Token::Delim('/', _)) = self.peek() {
+                    self.eat(1);
+                    match self.peek()? {
+                        Token::Number(denominator, _) => {
+                            MediaValue::Ratio(number.value, denominator.value)
+                        }
+                        _ => return None,
+                    }
+                } else {
+                    return Some(MediaValue::Number(number.value));
+                }
+            }
+            Token::Dimension(number, unit, _) => MediaValue::Dimension {
+                value: number.value,
+                unit: unit.value.to_ascii_lowercase(),
+            },
+            Token::Ident(ident, _) => MediaValue::Ident(ident.value.to_ascii_lowercase()),
+            _ => return None,
+        };
+        self.eat(1);
+        Some(value)
+    }
+
+    fn parse_operator(&mut self) -> Option<RangeOperator> {
+        let operator = match self.peek()? {
+            Token::Delim('<', _) => RangeOperator::Lt,
+            Token::Delim('>', _) => RangeOperator::Gt,
+            Token::Delim('=', _) => RangeOperator::Eq,
+            _ => return None,
+        };
+        self.eat(1);
+        // "<=" without space between
+        if operator != RangeOperator::Eq {
+            if let Some(Token::Delim('=', _)) = self.tokens.get(self.index) {
+                self.eat(1);
+                return Some(match operator {
+                    RangeOperator::Lt => RangeOperator::Le,
+                    _ => RangeOperator::Ge,
+                });
+            }
+        }
+        Some(operator)
+    }
+}
+
+/// parse a media condition without media type, eg. of "@container"
+pub(super) fn parse_media_condition(tokens: &[&Token]) -> Option<MediaCondition> {
+    let mut parser = MediaParser::new(tokens);
+    let condition = parser.parse_condition(true)?;
+    if parser.at_end() {
+        Some(condition)
+    } else {
+        None
+    }
+}
+
+/// parse a comma separated media query list, None if a query is not valid,
+/// eg. with a less variable
+pub fn parse_media_query_list(tokens: &[&Token]) -> Option<MediaQueryList> {
+    let mut list = vec![];
+    let mut depth = 0;
+    let mut from = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+            Token::CloseParen(_) => depth -= 1,
+            Token::Comma(_) if depth == 0 => {
+                list.push(MediaParser::new(&tokens[from..index]).parse_query()?);
+                from = index + 1;
+            }
+            _ => {}
+        }
+    }
+    let mut parser = MediaParser::new(&tokens[from..]);
+    if parser.at_end() && list.is_empty() {
+        // "@media {}" is for all media
+        return Some(list);
+    }
+    list.push(parser.parse_query()?);
+    Some(list)
+}
+
+/// a device to evaluate media queries against, lengths are in CSS px
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Environment {
+    /// eg. "screen" or "print"
+    pub media_type: String,
+    /// viewport size
+    pub width: f64,
+    pub height: f64,
+    /// dots per px, eg. 2 for a retina display
+    pub resolution: f64,
+    /// bits per color component, 0 for a device without color
+    pub color: f64,
+    /// bits per pixel of a monochrome device, 0 for a color device
+    pub monochrome: f64,
+    /// "hover" or "none", also for "any-hover"
+    pub hover: String,
+    /// "fine", "coarse" or "none", also for "any-pointer"
+    pub pointer: String,
+    /// "light" or "dark"
+    pub prefers_color_scheme: String,
+    /// "no-preference" or "reduce"
+    pub prefers_reduced_motion: String,
+    /// font size for "em" and "rem"
+    pub font_size: f64,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            media_type: String::from("screen"),
+            width: 1024.0,
+            height: 768.0,
+            resolution: 1.0,
+            color: 8.0,
+            monochrome: 0.0,
+            hover: String::from("hover"),
+            pointer: String::from("fine"),
+            prefers_color_scheme: String::from("light"),
+            prefers_reduced_motion: String::from("no-preference"),
+            font_size: 16.0,
+        }
+    }
+}
+
+/// value of a media feature in an environment
+enum Actual {
+    Length(f64),
+    Resolution(f64),
+    Ratio(f64),
+    Number(f64),
+    Ident(String),
+}
+
+impl Environment {
+    fn feature(&self, name: &str) -> Option<Actual> {
+        let actual = match name {
+            "width" | "device-width" => Actual::Length(self.width),
+            "height" | "device-height" => Actual::Length(self.height),
+            "aspect-ratio" | "device-aspect-ratio" => Actual::Ratio(self.width / self.height),
+            "resolution" => Actual::Resolution(self.resolution),
+            "color" => Actual::Number(self.color),
+            "monochrome" => Actual::Number(self.monochrome),
+            "grid" => Actual::Number(0.0),
+            "orientation" if self.height >= self.width => Actual::Ident(String::from("portrait")),
+            "orientation" => Actual::Ident(String::from("landscape")),
+            "hover" | "any-hover" => Actual::Ident(self.hover.to_owned()),
+            "pointer" | "any-pointer" => Actual::Ident(self.pointer.to_owned()),
+            "prefers-color-scheme" => Actual::Ident(self.prefers_color_scheme.to_owned()),
+            "prefers-reduced-motion" => Actual::Ident(self.prefers_reduced_motion.to_owned()),
+            _ => return None,
+        };
+        Some(actual)
+    }
+
+    /// a value in the unit of the actual value, None if they don't fit
+    fn convert(&self, actual: &Actual, value: &MediaValue) -> Option<f64> {
+        let value = match (actual, value) {
+            (Actual::Length(_), MediaValue::Number(number)) if *number == 0.0 => 0.0,
+            (Actual::Length(_), MediaValue::Dimension { value, unit }) => {
+                value
+                    * match unit.as_str() {
+                        "px" => 1.0,
+                        "em" | "rem" => self.font_size,
+                        "vw" => self.width / 100.0,
+                        "vh" => self.height / 100.0,
+                        "in" => 96.0,
+                        "cm" => 96.0 / 2.54,
+                        "mm" => 96.0 / 25.4,
+                        "q" => 96.0 / 101.6,
+                        "pt" => 96.0 / 72.0,
+                        "pc" => 16.0,
+                        _ => return None,
+                    }
+            }
+            (Actual::Resolution(_), MediaValue::Dimension { value, unit }) => {
+                value
+                    * match unit.as_str() {
+                        "dppx" | "x" => 1.0,
+                        "dpi" => 1.0 / 96.0,
+                        "dpcm" => 2.54 / 96.0,
+                        _ => return None,
+                    }
+            }
+            (Actual::Ratio(_), MediaValue::Ratio(a, b)) => a / b,
+            (Actual::Ratio(_), MediaValue::Number(number))
+            | (Actual::Number(_), MediaValue::Number(number)) => *number,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// `actual operator value`, None if unknown
+    fn compare(&self, name: &str, operator: RangeOperator, value: &MediaValue) -> Option<bool> {
+        let actual = self.feature(name)?;
+        let number = match &actual {
+            Actual::Length(number)
+            | Actual::Resolution(number)
+            | Actual::Ratio(number)
+            | Actual::Number(number) => *number,
+            Actual::Ident(ident) => {
+                return match value {
+                    MediaValue::Ident(value) if operator == RangeOperator::Eq => {
+                        Some(ident == value)
+                    }
+                    _ => None,
+                };
+            }
+        };
+        let value = self.convert(&actual, value)?;
+        Some(match operator {
+            RangeOperator::Lt => number < value,
+            RangeOperator::Le => number <= value,
+            RangeOperator::Gt => number > value,
+            RangeOperator::Ge => number >= value,
+            RangeOperator::Eq => number == value,
+        })
+    }
+
+    fn evaluate_feature(&self, feature: &MediaFeature) -> Option<bool> {
+        match feature {
+            MediaFeature::Boolean { name } => match self.feature(name)? {
+                Actual::Ident(ident) => Some(ident != "none" && ident != "no-preference"),
+                Actual::Length(number)
+                | Actual::Resolution(number)
+                | Actual::Ratio(number)
+                | Actual::Number(number) => Some(number != 0.0),
+            },
+            MediaFeature::Plain { name, value } => {
+                if let Some(name) = name.strip_prefix("min-") {
+                    self.compare(name, RangeOperator::Ge, value)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    self.compare(name, RangeOperator::Le, value)
+                } else {
+                    self.compare(name, RangeOperator::Eq, value)
+                }
+            }
+            MediaFeature::Range {
+                name,
+                operator,
+                value,
+            } => self.compare(name, *operator, value),
+            MediaFeature::Interval {
+                name,
+                start,
+                start_operator,
+                end_operator,
+                end,
+            } => {
+                let start = self.compare(name, start_operator.flip(), start)?;
+                let end = self.compare(name, *end_operator, end)?;
+                Some(start && end)
+            }
+        }
+    }
+
+    /// three-valued logic of media queries level 4, None for unknown
+    fn evaluate(&self, condition: &MediaCondition) -> Option<bool> {
+        match condition {
+            MediaCondition::Feature(feature) => self.evaluate_feature(feature),
+            MediaCondition::Not(condition) => self.evaluate(condition).map(|result| !result),
+            MediaCondition::And(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|condition| self.evaluate(condition))
+                    .collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            MediaCondition::Or(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|condition| self.evaluate(condition))
+                    .collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+            MediaCondition::GeneralEnclosed(_) | MediaCondition::Variable(_) => None,
+        }
+    }
+}
+
+impl MediaQuery {
+    /// whether the query applies to the environment,
+    /// a query with unknown features or values is false, and still false after "not"
+    pub fn matches(&self, environment: &Environment) -> bool {
+        let media_type = match &self.media_type {
+            Some(media_type) => {
+                media_type == "all" || environment.media_type.eq_ignore_ascii_case(media_type)
+            }
+            None => true,
+        };
+        let result = match &self.condition {
+            Some(condition) if media_type => environment.evaluate(condition),
+            _ => Some(media_type),
+        };
+        match (result, &self.modifier) {
+            (Some(result), Some(MediaModifier::Not)) => !result,
+            (Some(result), _) => result,
+            (None, _) => false,
+        }
+    }
+}
+
+/// whether any query of the list applies to the environment, an empty list applies to all
+pub fn matches_media(list: &[MediaQuery], environment: &Environment) -> bool {
+    list.is_empty() || list.iter().any(|query| query.matches(environment))
+}
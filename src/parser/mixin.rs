@@ -0,0 +1,351 @@
+use super::{
+    is_trivia, leading_trivia, raw_of, raw_value, search_important, trailing_trivia, Context,
+    NodeType, Parser, Raws,
+};
+use crate::lexer::{Location, Token};
+use serde::{Deserialize, Serialize};
+
+/// argument of a less mixin call, eg. "@color: red" or "2px"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixinArgument {
+    /// name with "@" of a named argument
+    pub name: Option<String>,
+    pub value: String,
+}
+
+/// mixin head found by looking ahead, eg. "#ns > .mixin(1px)"
+struct MixinHead {
+    /// eg. ["#ns", ".mixin"]
+    path: Vec<String>,
+    /// tokens inside "()"
+    args: Option<(usize, usize)>,
+    /// index after the head
+    end: usize,
+}
+
+/// less params are separated by ";" if there is any, otherwise by ","
+fn split_params(tokens: &[Token]) -> Vec<&[Token]> {
+    let top_level = |separator: fn(&Token) -> bool| {
+        let mut parts = vec![];
+        let mut depth = 0;
+        let mut from = 0;
+        for (index, token) in tokens.iter().enumerate() {
+            match token {
+                Token::OpenParen(_)
+                | Token::Function(_, _)
+                | Token::OpenSquare(_)
+                | Token::OpenCurly(_) => depth += 1,
+                Token::CloseParen(_) | Token::CloseSquare(_) | Token::CloseCurly(_) => depth -= 1,
+                token if depth == 0 && separator(token) => {
+                    parts.push(&tokens[from..index]);
+                    from = index + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push(&tokens[from..]);
+        parts
+    };
+    let parts = top_level(|token| matches!(token, Token::Semicolon(_)));
+    let parts = if parts.len() > 1 {
+        parts
+    } else {
+        top_level(|token| matches!(token, Token::Comma(_)))
+    };
+    let parts: Vec<&[Token]> = parts
+        .into_iter()
+        .map(|part| {
+            let lead = leading_trivia(part);
+            &part[lead..part.len() - trailing_trivia(&part[lead..])]
+        })
+        .collect();
+    // "()" and a trailing separator, eg. ".m(1; 2;)"
+    match parts.split_last() {
+        Some(([], rest)) => rest.to_vec(),
+        _ => parts,
+    }
+}
+
+/// "@name" of "@name...", or "" of "...", as the rest param
+fn rest_param(tokens: &[Token]) -> Option<String> {
+    let (name, dots) = match tokens {
+        [Token::AtKeyword(name, _), dots @ ..] => (format!("@{}", name.value), dots),
+        dots => (String::new(), dots),
+    };
+    match dots {
+        [Token::Delim('.', _), Token::Delim('.', _), Token::Delim('.', _)] => Some(name),
+        _ => None,
+    }
+}
+
+/// "@name" and the tokens after ":" of "@name: value"
+fn named(tokens: &[Token]) -> Option<(String, &[Token])> {
+    match tokens {
+        [Token::AtKeyword(name, _), rest @ ..] => {
+            let lead = leading_trivia(rest);
+            match rest.get(lead) {
+                Some(Token::Colon(_)) => Some((format!("@{}", name.value), &rest[lead + 1..])),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Parser<'_> {
+    /// index of the first token which is not whitespace or comment from `index`
+    fn skip_trivia(&self, mut index: usize) -> usize {
+        while let Some(token) = self.tokens.get(index) {
+            if !is_trivia(token) {
+                break;
+            }
+            index += 1;
+        }
+        index
+    }
+
+    /// look ahead for a mixin head from the current token
+    fn search_mixin_head(&self) -> Option<MixinHead> {
+        let tokens = self.tokens;
+        let mut index = self.token_counter;
+        let mut path = vec![];
+        let mut open = false;
+        loop {
+            match (tokens.get(index)?, tokens.get(index + 1)) {
+                // detached ruleset call, eg. "@detached();"
+                (Token::AtKeyword(name, _), Some(Token::OpenParen(_))) if path.is_empty() => {
+                    path.push(format!("@{}", name.value));
+                    index += 2;
+                    open = true;
+                    break;
+                }
+                (Token::Hash(name, _, _), _) => {
+                    path.push(format!("#{}", name.value));
+                    index += 1;
+                }
+                (Token::Delim('.', _), Some(Token::Ident(name, _))) => {
+                    path.push(format!(".{}", name.value));
+                    index += 2;
+                }
+                (Token::Delim('.', _), Some(Token::Function(name, _))) => {
+                    path.push(format!(".{}", name.value));
+                    index += 2;
+                    open = true;
+                    break;
+                }
+                _ => return None,
+            }
+            match tokens.get(index) {
+                Some(Token::OpenParen(_)) => {
+                    index += 1;
+                    open = true;
+                    break;
+                }
+                Some(Token::Hash(_, _, _)) | Some(Token::Delim('.', _)) => {}
+                // namespace separated by whitespace or ">", eg. "#ns > .mixin"
+                Some(token) if is_trivia(token) || matches!(token, Token::Delim('>', _)) => {
+                    let mut next = index;
+                    while let Some(token) = tokens.get(next) {
+                        if !is_trivia(token) && !matches!(token, Token::Delim('>', _)) {
+                            break;
+                        }
+                        next += 1;
+                    }
+                    match (tokens.get(next), tokens.get(next + 1)) {
+                        (Some(Token::Hash(_, _, _)), _)
+                        | (Some(Token::Delim('.', _)), Some(Token::Ident(_, _)))
+                        | (Some(Token::Delim('.', _)), Some(Token::Function(_, _))) => index = next,
+                        _ => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        let args = if open {
+            let from = index;
+            let mut depth = 1;
+            while depth > 0 {
+                match tokens.get(index)? {
+                    Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+                    Token::CloseParen(_) => depth -= 1,
+                    _ => {}
+                }
+                index += 1;
+            }
+            Some((from, index - 1))
+        } else {
+            None
+        };
+        Some(MixinHead {
+            path,
+            args,
+            end: index,
+        })
+    }
+
+    /// parse a less mixin definition or call, or a detached ruleset call,
+    /// None if the current tokens are not one, eg. a rule ".a {}" or ".a, .b {}"
+    pub(super) fn parse_mixin(&mut self, before: &mut String) -> Option<NodeType> {
+        let head = self.search_mixin_head()?;
+        let next = self.skip_trivia(head.end);
+        match self.tokens.get(next) {
+            Some(Token::Ident(ident, _)) if ident.value == "when" => {}
+            Some(Token::OpenCurly(_)) => {}
+            Some(Token::Delim('!', _))
+            | Some(Token::Semicolon(_))
+            | Some(Token::CloseCurly(_))
+            | None => return self.parse_mixin_call(head, before),
+            _ => return None,
+        }
+        if head.path.len() > 1 || head.args.is_none() || head.path[0].starts_with('@') {
+            return None;
+        }
+        self.parse_mixin_definition(head, next, before)
+    }
+
+    fn parse_mixin_definition(
+        &mut self,
+        head: MixinHead,
+        next: usize,
+        before: &mut String,
+    ) -> Option<NodeType> {
+        let tokens = self.tokens;
+        // guard after "when" until "{"
+        let mut open = next;
+        loop {
+            match tokens.get(open)? {
+                Token::OpenCurly(_) => break,
+                Token::Semicolon(_) | Token::CloseCurly(_) => return None,
+                _ => open += 1,
+            }
+        }
+        let guard = if open > next {
+            self.guard(next + 1, open)
+        } else {
+            None
+        };
+        let mut params = vec![];
+        let mut defaults = vec![];
+        let mut rest = None;
+        if let Some((from, to)) = head.args {
+            for param in split_params(&tokens[from..to]) {
+                if let Some(name) = rest_param(param) {
+                    rest = Some(name);
+                } else if let Some((name, value)) = named(param) {
+                    params.push(name);
+                    defaults.push(Some(raw_value(value).0));
+                } else {
+                    // "@name", or a value to match, eg. "dark" of ".m(dark; @color)"
+                    params.push(raw_value(param).0);
+                    defaults.push(None);
+                }
+            }
+        }
+        let start = self.get_start();
+        let selector_tokens = &tokens[self.token_counter..open];
+        let selector_len = selector_tokens.len() - trailing_trivia(selector_tokens);
+        let (selector, raw) = raw_value(&selector_tokens[..selector_len]);
+        let between = raw_of(&selector_tokens[selector_len..]);
+        self.token_counter = open + 1; // eat "{"
+        self.context.push_back(Context::InBrace);
+        let block = self.parse_nodes();
+        self.context.pop_back();
+        let (after, semicolon) = block.raws();
+        Some(NodeType::MixinDefinition {
+            r#type: String::from("mixin"),
+            name: head.path[0].to_owned(),
+            params,
+            defaults,
+            rest,
+            guard,
+            selector,
+            nodes: block.nodes,
+            source: Location {
+                start,
+                end: self.get_end(),
+            },
+            raws: Raws {
+                before: Some(std::mem::take(before)),
+                between: Some(between),
+                after,
+                semicolon,
+                selector: raw,
+                ..Raws::default()
+            },
+        })
+    }
+
+    fn parse_mixin_call(&mut self, head: MixinHead, before: &mut String) -> Option<NodeType> {
+        let tokens = self.tokens;
+        let mut end = head.end;
+        while let Some(token) = tokens.get(end) {
+            match token {
+                Token::Semicolon(_) | Token::CloseCurly(_) => break,
+                _ => end += 1,
+            }
+        }
+        let semicolon = matches!(tokens.get(end), Some(Token::Semicolon(_)));
+        let call_tokens = &tokens[self.token_counter..end];
+        // whitespace before "}" is left to the block
+        let call_tokens = if semicolon {
+            call_tokens
+        } else {
+            &call_tokens[..call_tokens.len() - trailing_trivia(call_tokens)]
+        };
+        let important = search_important(call_tokens);
+        let mut raws = Raws {
+            before: Some(std::mem::take(before)),
+            ..Raws::default()
+        };
+        let selector_len = important.unwrap_or(call_tokens.len());
+        // only "!important" can follow the call
+        if self.skip_trivia(head.end) < self.token_counter + selector_len {
+            return None;
+        }
+        if let Some(index) = important {
+            let string = raw_of(&call_tokens[index..]);
+            if string != " !important" {
+                raws.important = Some(string);
+            }
+        }
+        let selector_tokens = &call_tokens[..selector_len];
+        let (selector, raw) = raw_value(selector_tokens);
+        raws.selector = raw;
+        let args = match head.args {
+            Some((from, to)) => split_params(&tokens[from..to])
+                .into_iter()
+                .map(|arg| match named(arg) {
+                    Some((name, value)) => MixinArgument {
+                        name: Some(name),
+                        value: raw_value(value).0,
+                    },
+                    None => MixinArgument {
+                        name: None,
+                        value: raw_value(arg).0,
+                    },
+                })
+                .collect(),
+            None => vec![],
+        };
+        let start = self.get_start();
+        self.token_counter += call_tokens.len();
+        if semicolon {
+            self.eat(1); // eat ";"
+        }
+        let mut path = head.path;
+        let name = path.pop()?;
+        Some(NodeType::MixinCall {
+            r#type: String::from("mixin-call"),
+            name,
+            namespace: path,
+            args,
+            important: important.is_some(),
+            selector,
+            source: Location {
+                start,
+                end: self.get_end(),
+            },
+            raws,
+        })
+    }
+}
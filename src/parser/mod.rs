@@ -1,30 +1,191 @@
-use super::lexer::{Position, Token};
+mod atrule;
+mod error;
+mod guard;
+mod keyframe;
+mod media;
+mod mixin;
+mod selector;
+mod supports;
+mod value;
+
+use super::lexer::{Location, Position, Token};
 use serde::{Deserialize, Serialize};
 use std::collections::LinkedList;
 
+pub use atrule::{parse_prelude, validate_atrule, AtrulePrelude, PageSelector};
+pub use error::{ParseError, ParseErrorKind};
+pub use guard::{parse_guard, search_when, Guard, GuardEvaluator};
+pub use keyframe::{is_keyframes, parse_keyframe_selector};
+pub use media::{
+    matches_media, parse_media_query_list, Environment, MediaCondition, MediaFeature,
+    MediaModifier, MediaQuery, MediaQueryList, MediaValue, RangeOperator,
+};
+pub use mixin::MixinArgument;
+pub use selector::{
+    parse_selector_list, AttributeOperator, Combinator, ComplexSelector, PseudoArgument,
+    SelectorList, SelectorPart, SimpleSelector,
+};
+pub use supports::{parse_supports_condition, SupportedFeatures, SupportsCondition};
+pub use value::{parse_component_values, ComponentValue};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NodeType {
     Root,
     Rule {
         r#type: String,
         selector: String,
+        /// parsed selector, None if it's not a valid selector, eg. a less mixin
+        selectors: Option<SelectorList>,
+        /// less guard after "when", eg. of "& when (@mode = dark)", kept in `selector`
+        guard: Option<Guard>,
         nodes: Vec<NodeType>,
+        source: Location,
+        raws: Raws,
+    },
+    /// a rule in "@keyframes", eg. "from, 50% {}"
+    Keyframe {
+        r#type: String,
+        selector: String,
+        /// offsets of the selector list from 0 to 1, "from" is 0 and "to" is 1
+        offsets: Vec<f32>,
+        /// timeline range name of each offset, eg. "entry" of "entry 10%"
+        ranges: Vec<Option<String>>,
+        nodes: Vec<NodeType>,
+        source: Location,
+        raws: Raws,
     },
     Atrule {
         r#type: String,
         name: String,
         params: String,
-        // source: Location,
-        value: Option<String>,
+        /// parsed params of known at-rules, eg. the media queries of "@media"
+        prelude: Option<AtrulePrelude>,
+        nodes: Option<Vec<NodeType>>,
+        source: Location,
+        raws: Raws,
+    },
+    /// less variable, eg. "@color: red;", or detached ruleset, eg. "@detached: { top: 0 }"
+    VariableDecl {
+        r#type: String,
+        /// the name without "@"
+        name: String,
+        /// empty for a detached ruleset
+        value: String,
+        /// parsed value, the raw string is kept in `value`
+        values: Vec<ComponentValue>,
+        important: Option<bool>,
+        /// nodes of a detached ruleset, or of a map, eg. "@config: { primary: blue }"
         nodes: Option<Vec<NodeType>>,
+        source: Location,
+        raws: Raws,
     },
     Decl {
         r#type: String,
         prop: String,
         value: String,
+        /// parsed value, the raw string is kept in `value`
+        values: Vec<ComponentValue>,
         important: Option<bool>,
+        source: Location,
+        raws: Raws,
     },
-    // Comment,
+    /// less mixin definition, eg. ".bordered(@width: 2px) {}"
+    MixinDefinition {
+        r#type: String,
+        /// eg. ".bordered"
+        name: String,
+        /// eg. "@width", or a value to match, eg. "dark"
+        params: Vec<String>,
+        /// default value of each param
+        defaults: Vec<Option<String>>,
+        /// eg. "@rest" of "@rest...", "" of "..."
+        rest: Option<String>,
+        /// condition after "when"
+        guard: Option<Guard>,
+        /// the definition before "{"
+        selector: String,
+        nodes: Vec<NodeType>,
+        source: Location,
+        raws: Raws,
+    },
+    /// less mixin call, eg. "#ns > .mixin(4px) !important;", or detached ruleset call,
+    /// eg. "@detached();"
+    MixinCall {
+        r#type: String,
+        /// eg. ".mixin", or "@detached"
+        name: String,
+        /// eg. ["#ns"]
+        namespace: Vec<String>,
+        args: Vec<MixinArgument>,
+        important: bool,
+        /// the call without important
+        selector: String,
+        source: Location,
+        raws: Raws,
+    },
+    Comment {
+        r#type: String,
+        text: String,
+        /// less `// comment`
+        inline: bool,
+        source: Location,
+        raws: Raws,
+    },
+}
+
+/// source text around the values of a node, as PostCSS `raws`
+///
+/// the values of a node with its raws give back the source byte-for-byte
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Raws {
+    /// whitespace before the node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// between prop and value with ":", or between selector or params and "{" or ";"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub between: Option<String>,
+    /// whitespace before the "}" of a block, or the end of input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// the last node in the block ends with ";"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub semicolon: Option<bool>,
+    /// whitespace between at-rule name and params
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after_name: Option<String>,
+    /// spelling of important when it's not " !important", eg. " ! important"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub important: Option<String>,
+    /// whitespace after "/*" or "//"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<String>,
+    /// whitespace before "*/"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selector: Option<RawValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<RawValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<RawValue>,
+}
+
+/// source text of a selector, value or params when it differs from the node's,
+/// eg. with comments inside
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawValue {
+    pub value: String,
+    pub raw: String,
+}
+
+/// parsed nodes with the errors recovered from on the way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseResult {
+    pub nodes: Vec<NodeType>,
+    /// `after` and `semicolon` of the root
+    pub raws: Raws,
+    pub errors: Vec<ParseError>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,23 +194,121 @@ enum Context {
     InBrace, // {}
     InParen, // ()
     WaitBraceOrColon,
-    WaitValue,
+}
+
+/// nodes of a block with the raws of its end
+struct Block {
+    nodes: Vec<NodeType>,
+    after: String,
+    semicolon: bool,
+}
+
+impl Block {
+    fn raws(&self) -> (Option<String>, Option<bool>) {
+        let semicolon = if self.nodes.is_empty() {
+            None
+        } else {
+            Some(self.semicolon)
+        };
+        (Some(self.after.to_owned()), semicolon)
+    }
+}
+
+/// value after ":", the tokens `from..to` without whitespace and comments around
+struct Value {
+    /// whitespace and comments after ":"
+    between: String,
+    from: usize,
+    to: usize,
+    end: Position,
+}
+
+fn is_trivia(token: &Token) -> bool {
+    matches!(token, Token::Whitespace(_, _) | Token::Comment(_, _))
+}
+
+fn raw_of(tokens: &[Token]) -> String {
+    tokens.iter().map(|token| token.raw()).collect()
+}
+
+/// count of whitespace and comments at the start of tokens
+fn leading_trivia(tokens: &[Token]) -> usize {
+    tokens.iter().take_while(|token| is_trivia(token)).count()
+}
+
+/// count of whitespace and comments at the end of tokens
+fn trailing_trivia(tokens: &[Token]) -> usize {
+    tokens
+        .iter()
+        .rev()
+        .take_while(|token| is_trivia(token))
+        .count()
+}
+
+/// text of tokens without comments, with the raw text if it differs
+fn raw_value(tokens: &[Token]) -> (String, Option<RawValue>) {
+    let raw = raw_of(tokens);
+    let value: String = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Comment(_, _)))
+        .map(|token| token.raw())
+        .collect();
+    let value = value.trim().to_owned();
+    if value == raw {
+        (value, None)
+    } else {
+        (value.to_owned(), Some(RawValue { value, raw }))
+    }
+}
+
+/// processing exclamation mark ambiguity
+///
+/// search for important at the end of value, accept space before "important" keyword,
+/// return the index where important starts, including the spaces before "!"
+///
+/// # Examples
+///
+/// eg. "white !important" -> " !important"
+///
+/// eg. "white ! important " -> " ! important "
+fn search_important(tokens: &[Token]) -> Option<usize> {
+    let mut index = tokens.len() - trailing_trivia(tokens);
+    match index.checked_sub(1).and_then(|i| tokens.get(i)) {
+        Some(Token::Ident(string, _)) if string.value.eq_ignore_ascii_case("important") => {
+            index -= 1
+        }
+        _ => return None,
+    }
+    index -= trailing_trivia(&tokens[..index]);
+    match index.checked_sub(1).and_then(|i| tokens.get(i)) {
+        Some(Token::Delim('!', _)) => index -= 1,
+        _ => return None,
+    }
+    while let Some(Token::Whitespace(_, _)) = index.checked_sub(1).and_then(|i| tokens.get(i)) {
+        index -= 1;
+    }
+    Some(index)
 }
 
 pub struct Parser<'t> {
     context: LinkedList<Context>,
     tokens: &'t Vec<Token>,
     token_counter: usize,
+    errors: Vec<ParseError>,
+    /// stop at the first error instead of recovering
+    strict: bool,
 }
 
 impl Parser<'_> {
-    pub fn new(tokens: &Vec<Token>) -> Parser {
+    pub fn new(tokens: &Vec<Token>) -> Parser<'_> {
         let mut context = LinkedList::new();
         context.push_back(Context::Initial);
         Parser {
             tokens,
             context,
             token_counter: 0,
+            errors: vec![],
+            strict: false,
         }
     }
 
@@ -63,259 +322,494 @@ impl Parser<'_> {
         self.token_counter += len;
     }
 
-    fn get_start(&mut self) -> Position {
-        match self.tokens.get(self.token_counter).unwrap() {
-            Token::Punctuator(_, location) => location.to_owned().start,
-            _ => unreachable!(),
+    /// start position of the current token
+    fn get_start(&self) -> Position {
+        match self.tokens.get(self.token_counter) {
+            Some(token) => token.location().start.to_owned(),
+            None => self.get_end(),
         }
     }
 
-    /// processing exclamation mark ambiguity
-    ///
-    /// search for important, accept space before "important" keyword
-    ///
-    /// # Examples
-    ///
-    /// eg. "white !important;" -> return "!important"
-    ///
-    /// eg. "white ! important;" -> return "! important"
-    fn search_important(&mut self) -> Option<String> {
-        let saved = self.token_counter;
-        self.eat(1); // eat "!"
-        let mut text = String::new();
-        loop {
-            if let Some(token) = self.tokens.get(self.token_counter) {
-                match token {
-                    Token::Punctuator(string, _) => match &string[..] {
-                        ";" => {
-                            return Some(text);
-                        }
-                        _ => {
-                            self.token_counter = saved;
-                            return None;
-                        }
-                    },
-                    Token::Word(string, _) => match &string[..] {
-                        "important" => {
-                            text.push_str(string);
-                            self.eat(1);
-                        }
-                        _ => {
-                            self.token_counter = saved;
-                            return None;
-                        }
-                    },
-                    Token::Space(string, _) => {
-                        text.push_str(string);
-                        self.eat(1);
-                    }
-                    _ => {
-                        self.token_counter = saved;
-                        return None;
+    /// end position of the last eaten token
+    fn get_end(&self) -> Position {
+        match self
+            .token_counter
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+        {
+            Some(token) => token.location().end.to_owned(),
+            None => Position::default(),
+        }
+    }
+
+    /// location of the current token, or the end of input
+    fn get_location(&self) -> Location {
+        match self.tokens.get(self.token_counter) {
+            Some(token) => token.location().to_owned(),
+            None => {
+                let end = self.get_end();
+                Location {
+                    start: end.to_owned(),
+                    end,
+                }
+            }
+        }
+    }
+
+    /// drop the contexts pushed above `depth`
+    fn reset_context(&mut self, depth: usize) {
+        while self.context.len() > depth {
+            self.context.pop_back();
+        }
+    }
+
+    /// record an error, in strict mode the rest of input is skipped
+    fn error(&mut self, kind: ParseErrorKind, message: &str, span: Location) {
+        if self.strict {
+            if self.errors.is_empty() {
+                self.errors.push(ParseError {
+                    kind,
+                    message: message.to_owned(),
+                    span,
+                });
+            }
+            self.token_counter = self.tokens.len();
+        } else {
+            self.errors.push(ParseError {
+                kind,
+                message: message.to_owned(),
+                span,
+            });
+        }
+    }
+
+    /// skip a `{}` block, the current token should be "{"
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.token_counter) {
+            self.eat(1);
+            match token {
+                Token::OpenCurly(_) => depth += 1,
+                Token::CloseCurly(_) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
                     }
                 }
+                _ => {}
             }
         }
     }
 
-    /// return Option(value, position, important)
-    fn parse_value(&mut self) -> Option<(String, Position, bool)> {
-        while let Some(Token::Space(_, _)) = self.tokens.get(self.token_counter) {
-            self.eat(1); // eat spaces
+    /// error recovery, skip to the next ";" or over the next `{}` block
+    ///
+    /// stop before a "}" closing the current block, so the block still ends normally
+    fn recover(&mut self) {
+        while let Some(token) = self.tokens.get(self.token_counter) {
+            match token {
+                Token::Semicolon(_) => {
+                    self.eat(1);
+                    return;
+                }
+                Token::OpenCurly(_) => {
+                    self.skip_block();
+                    return;
+                }
+                Token::CloseCurly(_) => {
+                    return;
+                }
+                _ => self.eat(1),
+            }
         }
-        self.context.push_back(Context::WaitValue);
-        let mut text = String::new();
-        let mut important = false;
-        loop {
-            if let Some(token) = self.tokens.get(self.token_counter) {
-                match token {
-                    Token::Punctuator(string, location) => {
-                        match &string[..] {
-                            ";" => {
-                                // end with ;
-                                self.eat(1); // eat ";"
-                                let end_loc = location.to_owned();
-                                self.context.pop_back();
-                                return Some((text, end_loc.end, important));
-                            }
-                            "!" => {
-                                // lookback for space
-                                if let Some(Token::Space(_, _)) =
-                                    self.tokens.get(self.token_counter - 1)
-                                {
-                                    // TODO: add important location
-                                    if let Some(_) = self.search_important() {
-                                        important = true;
-                                    } else {
-                                        text.push_str(string);
-                                        self.eat(1);
-                                    }
-                                } else {
-                                    text.push_str(string);
-                                    self.eat(1);
-                                }
-                            }
-                            _ => {
-                                text.push_str(string);
-                                self.eat(1);
-                            }
+    }
+
+    /// build a comment node from a `/* */` or `//` comment token
+    fn parse_comment(&mut self, string: &str, location: &Location, before: String) -> NodeType {
+        self.eat(1); // eat comment
+        let (inner, inline) = if let Some(text) = string.strip_prefix("//") {
+            (text, true)
+        } else {
+            let text = string.strip_prefix("/*").unwrap_or(string);
+            (text.strip_suffix("*/").unwrap_or(text), false)
+        };
+        let text = inner.trim();
+        let (left, right) = if text.is_empty() {
+            (inner, "")
+        } else {
+            (
+                &inner[..inner.len() - inner.trim_start().len()],
+                &inner[inner.trim_end().len()..],
+            )
+        };
+        NodeType::Comment {
+            r#type: String::from("comment"),
+            text: text.to_owned(),
+            inline,
+            source: location.to_owned(),
+            raws: Raws {
+                before: Some(before),
+                left: Some(left.to_owned()),
+                right: Some(right.to_owned()),
+                ..Raws::default()
+            },
+        }
+    }
+
+    /// parse value after ":", end with ";" or before the "}" of block
+    ///
+    /// whitespace and comments before "}" are left to the block
+    fn parse_value(&mut self) -> Option<Value> {
+        let from = self.token_counter;
+        let mut to = from;
+        let mut semicolon = false;
+        while let Some(token) = self.tokens.get(to) {
+            match token {
+                Token::Semicolon(_) => {
+                    semicolon = true;
+                    break;
+                }
+                Token::CloseCurly(_) => break,
+                _ => to += 1,
+            }
+        }
+        let tokens = &self.tokens[from..to];
+        let len = if semicolon {
+            tokens.len()
+        } else {
+            tokens.len() - trailing_trivia(tokens)
+        };
+        let lead = leading_trivia(&tokens[..len]);
+        if lead == len && !semicolon && to == self.tokens.len() {
+            // end of input without value
+            return None;
+        }
+        let between = raw_of(&tokens[..lead]);
+        let end = if semicolon {
+            self.token_counter = to + 1; // eat value and ";"
+            self.get_end()
+        } else if lead < len {
+            self.token_counter = from + len; // eat value
+            self.get_end()
+        } else {
+            self.get_end()
+        };
+        Some(Value {
+            between,
+            from: from + lead,
+            to: from + len,
+            end,
+        })
+    }
+
+    fn parse_atrule(&mut self, name: &str, before: String) -> Option<NodeType> {
+        let start = self.get_start();
+        let depth = self.context.len();
+        let name = name.to_owned();
+        self.eat(1); // eat "@name"
+        let mut raws = Raws {
+            before: Some(before),
+            ..Raws::default()
+        };
+        if let Some(Token::Colon(_)) = self.tokens.get(self.token_counter) {
+            // less variable, eg. "@color: red;"
+            self.eat(1); // eat ":"
+            let open = self.token_counter + leading_trivia(&self.tokens[self.token_counter..]);
+            if let Some(Token::OpenCurly(_)) = self.tokens.get(open) {
+                // detached ruleset, eg. "@detached: { top: 0 }"
+                raws.between = Some(raw_of(&self.tokens[self.token_counter..open]));
+                self.token_counter = open + 1; // eat "{"
+                self.context.push_back(Context::InBrace);
+                let block = self.parse_nodes();
+                self.context.pop_back();
+                let (after, semicolon) = block.raws();
+                raws.after = after;
+                raws.semicolon = semicolon;
+                return Some(NodeType::VariableDecl {
+                    r#type: String::from("variable"),
+                    name,
+                    value: String::new(),
+                    values: vec![],
+                    important: None,
+                    nodes: Some(block.nodes),
+                    source: Location {
+                        start,
+                        end: self.get_end(),
+                    },
+                    raws,
+                });
+            }
+            if let Some(value) = self.parse_value() {
+                let value_tokens = &self.tokens[value.from..value.to];
+                raws.between = Some(format!(":{}", value.between));
+                let important = search_important(value_tokens);
+                let value_tokens = match important {
+                    Some(index) => {
+                        let string = raw_of(&value_tokens[index..]);
+                        if string != " !important" {
+                            raws.important = Some(string);
                         }
+                        &value_tokens[..index]
                     }
-                    Token::Paren(string, _)
-                    | Token::Hex(string, _)
-                    | Token::Number(string, _)
-                    | Token::String(string, _)
-                    | Token::Space(string, _)
-                    | Token::Other(string)
-                    | Token::Word(string, _) => {
-                        text.push_str(string);
-                        self.eat(1);
-                    }
-                    Token::EndLine(location) => {
-                        // end without ";"
-                        let end_loc = location.to_owned();
-                        self.eat(1);
-                        self.context.pop_back();
-                        return Some((text, end_loc.end, important));
-                    }
-                }
+                    None => value_tokens,
+                };
+                let (real_value, raw) = raw_value(value_tokens);
+                raws.value = raw;
+                let variable = NodeType::VariableDecl {
+                    r#type: String::from("variable"),
+                    name,
+                    value: real_value,
+                    values: parse_component_values(value_tokens),
+                    important: important.map(|_| true),
+                    nodes: None,
+                    source: Location {
+                        start,
+                        end: value.end,
+                    },
+                    raws,
+                };
+                return Some(variable);
             } else {
+                let location = self.get_location();
+                self.error(
+                    ParseErrorKind::UnexpectedEof,
+                    "unexpected end of input in variable value",
+                    location,
+                );
                 return None;
             }
         }
-    }
-
-    fn parse_atrule(&mut self) -> Option<NodeType> {
-        let _ = self.get_start();
-        self.eat(1); // eat "@"
         self.context.push_back(Context::WaitBraceOrColon);
-        let mut text = String::new();
-        match self.tokens.get(self.token_counter) {
-            Some(Token::Word(name, _)) => {
-                let name = name.to_owned();
-                self.eat(1); // eat name
-                'atrule: loop {
-                    if let Some(token) = self.tokens.get(self.token_counter) {
-                        match token {
-                            Token::Paren(string, _) => match &string[..] {
-                                "(" => {
-                                    // TODO: extract parse paren
-                                    // InParen
-                                    text.push_str(string);
-                                    self.eat(1);
-                                    self.context.push_back(Context::InParen);
-                                }
-                                ")" => {
-                                    // pop InParen
-                                    if let Some(Context::InParen) = self.get_context() {
-                                        text.push_str(string);
-                                        self.context.pop_back();
-                                    } else {
-                                        // TODO: error
-                                    }
-                                    self.eat(1);
-                                }
-                                "{" => {
-                                    // end, shift to InBrace
-                                    self.eat(1);
-                                    self.context.push_back(Context::InBrace);
-                                    let nodes = self.parse_nodes();
-                                    self.context.pop_back(); // pop InBrace
-                                    let atrule = NodeType::Atrule {
-                                        r#type: String::from("atrule"),
-                                        name,
-                                        params: String::from(text.trim()),
-                                        value: None,
-                                        nodes: Some(nodes),
-                                    };
-                                    return Some(atrule);
-                                }
-                                _ => {
-                                    text.push_str(string);
-                                    self.eat(1);
-                                }
-                            },
-                            Token::Punctuator(string, _) => match &string[..] {
-                                ";" => {
-                                    self.eat(1); // eat ";"
-                                    self.context.pop_back(); // pop WaitBraceOrColon
-                                    if text.len() > 0 {
-                                        let atrule = NodeType::Atrule {
-                                            r#type: String::from("atrule"),
-                                            name,
-                                            params: text.trim().to_owned(),
-                                            value: None,
-                                            nodes: None,
-                                        };
-                                        return Some(atrule);
-                                    } else {
-                                        // TODO: error, empty params
-                                        return None;
-                                    }
-                                }
-                                ":" => {
-                                    // TODO: parse value
-                                    if let Some(Context::InParen) = self.get_context() {
-                                        self.eat(1);
-                                    } else {
-                                        self.context.pop_back(); // pop WaitBraceOrColon
-                                        self.eat(1); // eat ":"
-                                        if let Some((value, _, important)) = self.parse_value() {
-                                            let real_value = if important {
-                                                let mut v = value.to_owned();
-                                                v.push_str("!important");
-                                                v
-                                            } else {
-                                                value.to_owned()
-                                            };
-                                            let atrule = NodeType::Atrule {
-                                                r#type: String::from("atrule"),
-                                                name,
-                                                // source: Location {
-                                                //     start: start,
-                                                //     end: end,
-                                                // },
-                                                params: real_value.to_owned(),
-                                                value: Some(real_value.to_owned()),
-                                                nodes: None,
-                                            };
-                                            return Some(atrule);
-                                        } else {
-                                            // TODO: error
-                                            return None;
-                                        }
-                                    }
-                                }
-                                _ => {
-                                    text.push_str(string);
-                                    self.eat(1);
-                                }
-                            },
-                            Token::Hex(string, _)
-                            | Token::Number(string, _)
-                            | Token::String(string, _)
-                            | Token::Space(string, _)
-                            | Token::Other(string)
-                            | Token::Word(string, _) => {
-                                text.push_str(string);
-                                self.eat(1);
-                            }
-                            Token::EndLine(_) => {
-                                // TODO: error
-                                self.eat(1);
-                            }
-                        }
+        let from = self.token_counter;
+        while let Some(token) = self.tokens.get(self.token_counter) {
+            match token {
+                Token::OpenParen(_) | Token::Function(_, _) => {
+                    // TODO: extract parse paren
+                    // InParen
+                    self.eat(1);
+                    self.context.push_back(Context::InParen);
+                }
+                Token::CloseParen(location) => {
+                    // pop InParen
+                    if let Some(Context::InParen) = self.get_context() {
+                        self.context.pop_back();
                     } else {
-                        break 'atrule;
+                        self.error(
+                            ParseErrorKind::UnexpectedToken,
+                            "unexpected \")\" in at-rule params",
+                            location.to_owned(),
+                        );
                     }
+                    self.eat(1);
                 }
+                Token::OpenCurly(location) => {
+                    if let Some(Context::InParen) = self.get_context() {
+                        self.error(
+                            ParseErrorKind::UnclosedParen,
+                            "expected \")\" before \"{\"",
+                            location.to_owned(),
+                        );
+                    }
+                    // end, shift to InBrace
+                    let params = self.split_params(from, &mut raws);
+                    let prelude = parse_prelude(&name, &self.tokens[from..self.token_counter]);
+                    self.eat(1);
+                    self.reset_context(depth); // pop WaitBraceOrColon
+                    self.context.push_back(Context::InBrace);
+                    let mut block = self.parse_nodes();
+                    self.context.pop_back(); // pop InBrace
+                    if is_keyframes(&name) {
+                        block.nodes = block
+                            .nodes
+                            .into_iter()
+                            .map(|node| self.keyframe(node))
+                            .collect();
+                    }
+                    let (after, semicolon) = block.raws();
+                    raws.after = after;
+                    raws.semicolon = semicolon;
+                    let atrule = NodeType::Atrule {
+                        r#type: String::from("atrule"),
+                        name,
+                        params,
+                        prelude,
+                        nodes: Some(block.nodes),
+                        source: Location {
+                            start,
+                            end: self.get_end(),
+                        },
+                        raws,
+                    };
+                    self.validate(&atrule);
+                    return Some(atrule);
+                }
+                Token::Semicolon(_) | Token::CloseCurly(_) => {
+                    // a "}" ends the statement of the last at-rule in block, and is left to the block
+                    let location = token.location().to_owned();
+                    let params = self.split_params(from, &mut raws);
+                    let prelude = parse_prelude(&name, &self.tokens[from..self.token_counter]);
+                    let end = if let Token::Semicolon(_) = token {
+                        self.eat(1); // eat ";"
+                        location.end.to_owned()
+                    } else {
+                        self.get_end()
+                    };
+                    self.reset_context(depth); // pop WaitBraceOrColon
+                    if !params.is_empty() {
+                        let atrule = NodeType::Atrule {
+                            r#type: String::from("atrule"),
+                            name,
+                            params,
+                            prelude,
+                            nodes: None,
+                            source: Location { start, end },
+                            raws,
+                        };
+                        self.validate(&atrule);
+                        return Some(atrule);
+                    } else {
+                        self.error(
+                            ParseErrorKind::EmptyAtruleParams,
+                            &format!("expected params for \"@{}\"", name),
+                            Location {
+                                start,
+                                end: location.end,
+                            },
+                        );
+                        return None;
+                    }
+                }
+                _ => self.eat(1),
             }
-            _ => {
-                // TODO: error
-                self.eat(1);
+        }
+        let location = self.get_location();
+        self.error(
+            ParseErrorKind::UnexpectedEof,
+            &format!("expected \";\" or \"{{\" after \"@{}\"", name),
+            location,
+        );
+        self.reset_context(depth);
+        None
+    }
+
+    /// turn a rule in "@keyframes" to a keyframe, report an invalid selector and "!important"
+    fn keyframe(&mut self, node: NodeType) -> NodeType {
+        let (selector, nodes, source, raws) = match node {
+            NodeType::Rule {
+                selector,
+                nodes,
+                source,
+                raws,
+                ..
+            } => (selector, nodes, source, raws),
+            node => return node,
+        };
+        let offsets = match parse_keyframe_selector(&selector) {
+            Some(offsets) => offsets,
+            None => {
+                self.error(
+                    ParseErrorKind::InvalidKeyframeSelector,
+                    &format!("invalid keyframe selector \"{}\"", selector),
+                    source.to_owned(),
+                );
+                return NodeType::Rule {
+                    r#type: String::from("rule"),
+                    selector,
+                    selectors: None,
+                    guard: None,
+                    nodes,
+                    source,
+                    raws,
+                };
             }
         };
-        None
+        for node in nodes.iter() {
+            if let NodeType::Decl {
+                prop,
+                important: Some(true),
+                source,
+                ..
+            } = node
+            {
+                self.error(
+                    ParseErrorKind::ImportantInKeyframe,
+                    &format!("\"!important\" of \"{}\" is ignored in keyframes", prop),
+                    source.to_owned(),
+                );
+            }
+        }
+        let (offsets, ranges) = offsets.into_iter().unzip();
+        NodeType::Keyframe {
+            r#type: String::from("keyframe"),
+            selector,
+            offsets,
+            ranges,
+            nodes,
+            source,
+            raws,
+        }
+    }
+
+    /// report errors of a standard at-rule, its params or descriptors
+    fn validate(&mut self, atrule: &NodeType) {
+        if let NodeType::Atrule {
+            name,
+            prelude,
+            nodes,
+            source,
+            ..
+        } = atrule
+        {
+            for error in validate_atrule(name, prelude, nodes, source) {
+                // descriptors are checked like values, a strict parse only fails on the syntax
+                if self.strict
+                    && matches!(
+                        error.kind,
+                        ParseErrorKind::InvalidDescriptor | ParseErrorKind::MissingDescriptor
+                    )
+                {
+                    continue;
+                }
+                self.error(error.kind, &error.message, error.span);
+            }
+        }
+    }
+
+    /// parse the less guard of tokens `from..to` after "when", report it if it's not valid
+    fn guard(&mut self, from: usize, to: usize) -> Option<Guard> {
+        let tokens = &self.tokens[from..to];
+        let guard = parse_guard(tokens);
+        if guard.is_none() {
+            let (text, _) = raw_value(tokens);
+            let span = match (tokens.first(), tokens.last()) {
+                (Some(first), Some(last)) => Location {
+                    start: first.location().start.to_owned(),
+                    end: last.location().end.to_owned(),
+                },
+                _ => match self.tokens.get(to) {
+                    Some(token) => token.location().to_owned(),
+                    None => self.get_location(),
+                },
+            };
+            self.error(
+                ParseErrorKind::InvalidGuard,
+                &format!("invalid guard \"{}\"", text),
+                span,
+            );
+        }
+        guard
+    }
+
+    /// at-rule params from token `from` to the current token,
+    /// whitespace and comments around go to `afterName` and `between`
+    fn split_params(&self, from: usize, raws: &mut Raws) -> String {
+        let tokens = &self.tokens[from..self.token_counter];
+        let len = tokens.len() - trailing_trivia(tokens);
+        let lead = leading_trivia(&tokens[..len]);
+        let (params, raw) = raw_value(&tokens[lead..len]);
+        raws.after_name = Some(raw_of(&tokens[..lead]));
+        raws.between = Some(raw_of(&tokens[len..]));
+        raws.params = raw;
+        params
     }
 
     /// processing colon ambiguity
@@ -324,198 +818,385 @@ impl Parser<'_> {
     fn search_sudoclass(&mut self) -> Option<String> {
         let saved = self.token_counter;
         let mut text = String::new();
-        text.push_str(":");
+        text.push(':');
         self.eat(1); // eat ":"
-        if let Some(Token::Punctuator(string, _)) = self.tokens.get(self.token_counter) {
-            if string == ":" {
-                text.push_str(string);
-                self.eat(1);
-                return Some(text);
-            }
+        if let Some(Token::Colon(_)) = self.tokens.get(self.token_counter) {
+            text.push(':');
+            self.eat(1);
+            return Some(text);
         }
+        // colons inside "()" or "[]" decide nothing, eg. "url(http://a.com)"
+        let mut paren_depth = 0;
         loop {
             if let Some(token) = self.tokens.get(self.token_counter) {
                 match token {
-                    Token::Paren(string, _) => match &string[..] {
-                        "{" => {
-                            // all previous texts are selector
-                            return Some(text);
-                        }
-                        _ => {
-                            text.push_str(string);
-                            self.eat(1);
-                        }
-                    },
-                    Token::Punctuator(string, _) => match &string[..] {
-                        ":" => {
-                            // all previous texts are selector
-                            return Some(text);
-                        }
-                        ";" => {
-                            // all previous texts are value
-                            self.token_counter = saved;
-                            return None;
-                        }
-                        _ => {
-                            text.push_str(string);
-                            self.eat(1);
-                        }
-                    },
-                    Token::String(string, _)
-                    | Token::Hex(string, _)
-                    | Token::Number(string, _)
-                    | Token::Space(string, _)
-                    | Token::Other(string)
-                    | Token::Word(string, _) => {
-                        text.push_str(string);
-                        self.eat(1);
+                    Token::OpenCurly(_) => {
+                        // all previous texts are selector
+                        return Some(text);
+                    }
+                    Token::Colon(_) if paren_depth <= 0 => {
+                        // all previous texts are selector
+                        return Some(text);
                     }
-                    Token::EndLine(_) => {
+                    Token::Semicolon(_) | Token::CloseCurly(_) => {
                         // all previous texts are value
                         self.token_counter = saved;
                         return None;
                     }
+                    Token::Comment(_, _) => {
+                        self.eat(1);
+                    }
+                    _ => {
+                        match token {
+                            Token::OpenParen(_) | Token::OpenSquare(_) | Token::Function(_, _) => {
+                                paren_depth += 1
+                            }
+                            Token::CloseParen(_) | Token::CloseSquare(_) => paren_depth -= 1,
+                            _ => {}
+                        }
+                        text.push_str(&token.raw());
+                        self.eat(1);
+                    }
                 }
+            } else {
+                self.token_counter = saved;
+                return None;
             }
         }
     }
 
-    fn parse_nodes(&mut self) -> Vec<NodeType> {
+    fn parse_nodes(&mut self) -> Block {
         // parse Initial/InBrace/WaitBraceOrColon context
+        let depth = self.context.len();
         let mut text = String::new();
+        let mut text_start = self.token_counter;
+        let mut start = self.get_start();
+        // whitespace before the next node, or before "}"
+        let mut before = String::new();
+        let mut semicolon = false;
         let mut nodes: Vec<NodeType> = vec![];
         loop {
             if let Some(token) = self.tokens.get(self.token_counter) {
+                if text.is_empty() {
+                    // selector or prop starts at the first token of the text
+                    start = token.location().start.to_owned();
+                    text_start = self.token_counter;
+                }
+                let wait_selector_or_prop = matches!(
+                    self.get_context(),
+                    Some(Context::Initial) | Some(Context::InBrace)
+                );
+                if text.is_empty()
+                    && wait_selector_or_prop
+                    && matches!(
+                        token,
+                        Token::Delim('.', _) | Token::Hash(_, _, _) | Token::AtKeyword(_, _)
+                    )
+                {
+                    if let Some(mixin) = self.parse_mixin(&mut before) {
+                        semicolon = matches!(mixin, NodeType::MixinCall { .. })
+                            && self.ends_with_semicolon();
+                        nodes.push(mixin);
+                        continue;
+                    }
+                }
                 match token {
-                    Token::Word(string, _) => {
-                        match self.get_context() {
-                            Some(Context::Initial) | Some(Context::InBrace) => {
-                                self.context.push_back(Context::WaitBraceOrColon);
-                            }
-                            _ => {}
+                    Token::Comment(string, location) => {
+                        if text.is_empty() {
+                            let comment =
+                                self.parse_comment(string, location, std::mem::take(&mut before));
+                            nodes.push(comment);
+                        } else {
+                            // comment inside a selector or prop, kept in raws
+                            self.eat(1);
                         }
-                        text.push_str(string);
-                        self.eat(1);
                     }
-                    Token::Space(string, _) => match self.get_context() {
-                        Some(Context::WaitBraceOrColon) => {
+                    Token::Whitespace(string, _) => {
+                        if text.is_empty() {
+                            before.push_str(string);
+                        } else if let Some(Context::WaitBraceOrColon) = self.get_context() {
                             text.push_str(string);
-                            self.eat(1);
                         }
-                        _ => {
-                            self.eat(1);
+                        self.eat(1);
+                    }
+                    Token::Cdo(_) | Token::Cdc(_) if wait_selector_or_prop => {
+                        // "<!--" and "-->" are ignored between rules
+                        before.push_str(&token.raw());
+                        self.eat(1);
+                    }
+                    Token::AtKeyword(name, _) if wait_selector_or_prop => {
+                        if let Some(rule) =
+                            self.parse_atrule(&name.value, std::mem::take(&mut before))
+                        {
+                            nodes.push(rule);
+                            semicolon = self.ends_with_semicolon();
                         }
-                    },
-                    Token::Punctuator(string, _) => match &string[..] {
-                        "@" => match self.get_context() {
-                            Some(Context::Initial) | Some(Context::InBrace) => {
-                                if let Some(rule) = self.parse_atrule() {
-                                    nodes.push(rule);
-                                }
-                            }
-                            _ => {
-                                self.eat(1);
-                            }
-                        },
-                        "*" | "&" => {
-                            match self.get_context() {
-                                Some(Context::Initial) | Some(Context::InBrace) => {
-                                    self.context.push_back(Context::WaitBraceOrColon);
-                                }
-                                _ => {}
-                            }
-                            text.push_str(string);
-                            self.eat(1);
+                    }
+                    Token::Delim('@', location) if wait_selector_or_prop => {
+                        self.error(
+                            ParseErrorKind::MissingAtruleName,
+                            "expected at-rule name after \"@\"",
+                            location.to_owned(),
+                        );
+                        self.recover();
+                    }
+                    Token::Semicolon(location) => {
+                        if !text.trim().is_empty() {
+                            // eg. "color red;"
+                            self.error(
+                                ParseErrorKind::MissingColon,
+                                &format!("expected \":\" after \"{}\"", text.trim()),
+                                Location {
+                                    start: start.to_owned(),
+                                    end: location.end.to_owned(),
+                                },
+                            );
+                            text.clear();
+                            self.reset_context(depth);
+                        } else {
+                            // semicolon without declaration, eg. "a { color: red;; }"
+                            before.push(';');
                         }
-                        ":" => {
-                            // TODO: parse value and parse sudo class
-                            if let Some(string) = self.search_sudoclass() {
-                                match self.get_context() {
-                                    Some(Context::Initial) | Some(Context::InBrace) => {
-                                        self.context.push_back(Context::WaitBraceOrColon);
+                        self.eat(1);
+                    }
+                    Token::Colon(location) => {
+                        if let Some(string) = self.search_sudoclass() {
+                            if wait_selector_or_prop {
+                                self.context.push_back(Context::WaitBraceOrColon);
+                            }
+                            text.push_str(&string);
+                        } else if text.trim().is_empty() {
+                            // eg. ": red;"
+                            self.error(
+                                ParseErrorKind::MissingProp,
+                                "expected prop before \":\"",
+                                location.to_owned(),
+                            );
+                            text.clear();
+                            self.reset_context(depth);
+                            self.recover();
+                        } else {
+                            self.reset_context(depth); // pop WaitBraceOrColon
+                            let prop_tokens = &self.tokens[text_start..self.token_counter];
+                            let prop_len = prop_tokens.len() - trailing_trivia(prop_tokens);
+                            let (prop, _) = raw_value(&prop_tokens[..prop_len]);
+                            self.eat(1); // eat ":"
+                            if let Some(value) = self.parse_value() {
+                                let value_tokens = &self.tokens[value.from..value.to];
+                                let mut raws = Raws {
+                                    before: Some(std::mem::take(&mut before)),
+                                    between: Some(format!(
+                                        "{}:{}",
+                                        raw_of(&prop_tokens[prop_len..]),
+                                        value.between
+                                    )),
+                                    ..Raws::default()
+                                };
+                                let important = search_important(value_tokens);
+                                let value_tokens = match important {
+                                    Some(index) => {
+                                        let string = raw_of(&value_tokens[index..]);
+                                        if string != " !important" {
+                                            raws.important = Some(string);
+                                        }
+                                        &value_tokens[..index]
                                     }
-                                    _ => {}
-                                }
-                                text.push_str(&string);
+                                    None => value_tokens,
+                                };
+                                let (value_text, raw) = raw_value(value_tokens);
+                                let values = parse_component_values(value_tokens);
+                                raws.value = raw;
+                                let decl = NodeType::Decl {
+                                    r#type: String::from("decl"),
+                                    prop,
+                                    value: value_text,
+                                    values,
+                                    important: important.map(|_| true),
+                                    source: Location {
+                                        start: start.to_owned(),
+                                        end: value.end,
+                                    },
+                                    raws,
+                                };
+                                nodes.push(decl);
+                                semicolon = self.ends_with_semicolon();
                             } else {
-                                self.context.pop_back(); // pop WaitBraceOrColon
-                                self.eat(1); // eat ":"
-                                if let Some((value, _, important)) = self.parse_value() {
-                                    let decl = NodeType::Decl {
-                                        r#type: String::from("decl"),
-                                        prop: text.to_owned(),
-                                        value,
-                                        important: if important { Some(true) } else { None },
+                                let location = self.get_location();
+                                self.error(
+                                    ParseErrorKind::UnexpectedEof,
+                                    "unexpected end of input in declaration value",
+                                    location,
+                                );
+                            }
+                            text.clear();
+                        }
+                    }
+                    Token::OpenCurly(location) => {
+                        match self.get_context() {
+                            Some(Context::WaitBraceOrColon) => {
+                                let selector_tokens = &self.tokens[text_start..self.token_counter];
+                                let selector_len =
+                                    selector_tokens.len() - trailing_trivia(selector_tokens);
+                                let (selector, raw) = raw_value(&selector_tokens[..selector_len]);
+                                let between = raw_of(&selector_tokens[selector_len..]);
+                                let (selectors, guard) =
+                                    match search_when(&selector_tokens[..selector_len]) {
+                                        Some(when) => {
+                                            let head = &selector_tokens[..when];
+                                            let head = &head[..head.len() - trailing_trivia(head)];
+                                            let guard = self.guard(
+                                                text_start + when + 1,
+                                                text_start + selector_len,
+                                            );
+                                            (parse_selector_list(head), guard)
+                                        }
+                                        None => (
+                                            parse_selector_list(&selector_tokens[..selector_len]),
+                                            None,
+                                        ),
                                     };
-                                    nodes.push(decl);
-                                } else {
-                                    // TODO: error
-                                }
+                                self.eat(1);
+                                // pop WaitBraceOrColon
+                                self.context.pop_back();
+                                self.context.push_back(Context::InBrace);
+                                let block = self.parse_nodes();
+                                // pop InBrace context end Brace
+                                self.context.pop_back();
+                                let (after, block_semicolon) = block.raws();
+                                let rule = NodeType::Rule {
+                                    r#type: String::from("rule"),
+                                    selector,
+                                    selectors,
+                                    guard,
+                                    nodes: block.nodes,
+                                    source: Location {
+                                        start: start.to_owned(),
+                                        end: self.get_end(),
+                                    },
+                                    raws: Raws {
+                                        before: Some(std::mem::take(&mut before)),
+                                        between: Some(between),
+                                        after,
+                                        semicolon: block_semicolon,
+                                        selector: raw,
+                                        ..Raws::default()
+                                    },
+                                };
                                 text.clear();
+                                nodes.push(rule);
+                                semicolon = false;
+                            }
+                            _ => {
+                                // block without selector, eg. "{ color: red; }"
+                                self.error(
+                                    ParseErrorKind::UnexpectedToken,
+                                    "expected selector before \"{\"",
+                                    location.to_owned(),
+                                );
+                                self.skip_block();
                             }
                         }
-                        _ => {
-                            text.push_str(string);
-                            self.eat(1);
+                    }
+                    Token::CloseCurly(location) => {
+                        if !text.trim().is_empty() {
+                            // eg. "a { color }"
+                            self.error(
+                                ParseErrorKind::MissingColon,
+                                &format!("expected \":\" after \"{}\"", text.trim()),
+                                Location {
+                                    start: start.to_owned(),
+                                    end: self.get_end(),
+                                },
+                            );
+                            text.clear();
+                            self.reset_context(depth);
                         }
-                    },
-                    Token::Paren(string, _) => match &string[..] {
-                        "{" => {
-                            match self.get_context() {
-                                Some(Context::WaitBraceOrColon) => {
-                                    self.eat(1);
-                                    // pop WaitBraceOrColon
-                                    self.context.pop_back();
-                                    self.context.push_back(Context::InBrace);
-                                    let parsed_nodes = self.parse_nodes();
-                                    // pop InBrace context end Brace
-                                    self.context.pop_back();
-                                    let rule = NodeType::Rule {
-                                        r#type: String::from("rule"),
-                                        selector: String::from(text.trim_end()),
-                                        nodes: parsed_nodes,
-                                    };
-                                    text.clear();
-                                    nodes.push(rule);
-                                }
-                                _ => {
-                                    // TODO erro?
-                                    self.eat(1);
-                                }
+                        match self.get_context() {
+                            Some(Context::InBrace) => {
+                                self.eat(1); // eat "}"
+                                return Block {
+                                    nodes,
+                                    after: before,
+                                    semicolon,
+                                };
                             }
-                        }
-                        "}" => {
-                            match self.get_context() {
-                                Some(Context::InBrace) => {
-                                    self.eat(1); // eat "}"
-                                    return nodes;
-                                }
-                                _ => {
-                                    // TODO error?
-                                    self.eat(1);
-                                }
+                            _ => {
+                                self.error(
+                                    ParseErrorKind::UnexpectedToken,
+                                    "unexpected \"}\"",
+                                    location.to_owned(),
+                                );
+                                self.eat(1);
                             }
                         }
-                        _ => {
-                            text.push_str(string);
-                            // TODO error?
-                            self.eat(1);
-                        }
-                    },
+                    }
                     _ => {
-                        // TODO error?
+                        if wait_selector_or_prop {
+                            self.context.push_back(Context::WaitBraceOrColon);
+                        }
+                        text.push_str(&token.raw());
                         self.eat(1);
                     }
                 }
             } else {
-                return nodes;
+                if !text.trim().is_empty() {
+                    self.error(
+                        ParseErrorKind::UnexpectedEof,
+                        &format!("unexpected end of input after \"{}\"", text.trim()),
+                        Location {
+                            start,
+                            end: self.get_end(),
+                        },
+                    );
+                }
+                if let Some(Context::InBrace) = self.context.iter().nth(depth - 1) {
+                    let location = self.get_location();
+                    self.error(
+                        ParseErrorKind::UnclosedBlock,
+                        "expected \"}\" before end of input",
+                        location,
+                    );
+                }
+                self.reset_context(depth);
+                return Block {
+                    nodes,
+                    after: before,
+                    semicolon,
+                };
             }
         }
     }
 
-    pub fn parse(&mut self) -> std::vec::Vec<NodeType> {
-        let nodes = self.parse_nodes();
-        dbg!(nodes.to_owned());
-        nodes
+    /// the last eaten token is ";"
+    fn ends_with_semicolon(&self) -> bool {
+        let last = self
+            .token_counter
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i));
+        matches!(last, Some(Token::Semicolon(_)))
+    }
+
+    pub fn parse(&mut self) -> ParseResult {
+        let block = self.parse_nodes();
+        let (after, semicolon) = block.raws();
+        ParseResult {
+            nodes: block.nodes,
+            raws: Raws {
+                after,
+                semicolon,
+                ..Raws::default()
+            },
+            errors: self.errors.to_owned(),
+        }
+    }
+
+    /// parse without recovery, fail with the first error
+    pub fn parse_strict(&mut self) -> Result<Vec<NodeType>, ParseError> {
+        self.strict = true;
+        let result = self.parse();
+        match result.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(result.nodes),
+        }
     }
 }
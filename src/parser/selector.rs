@@ -0,0 +1,530 @@
+use crate::lexer::{HashType, Token};
+use serde::{Deserialize, Serialize};
+
+/// selectors level 4, <https://www.w3.org/TR/selectors-4/>
+pub type SelectorList = Vec<ComplexSelector>;
+
+/// compound selectors joined by combinators, eg. "main > p.note"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComplexSelector {
+    pub parts: Vec<SelectorPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectorPart {
+    /// combinator before the compound, None for the first compound,
+    /// a relative selector starts with one, eg. "> a" in ":has(> a)"
+    pub combinator: Option<Combinator>,
+    pub compound: Vec<SimpleSelector>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Combinator {
+    /// whitespace
+    Descendant,
+    /// ">"
+    Child,
+    /// "+"
+    NextSibling,
+    /// "~"
+    SubsequentSibling,
+    /// "||"
+    Column,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SimpleSelector {
+    /// `namespace` is Some("") for "|a" and Some("*") for "*|a"
+    Type {
+        namespace: Option<String>,
+        name: String,
+    },
+    Universal {
+        namespace: Option<String>,
+    },
+    Class(String),
+    Id(String),
+    Attribute {
+        namespace: Option<String>,
+        name: String,
+        operator: Option<AttributeOperator>,
+        value: Option<String>,
+        /// "i" or "s" flag
+        modifier: Option<char>,
+    },
+    PseudoClass {
+        name: String,
+        argument: Option<PseudoArgument>,
+    },
+    PseudoElement {
+        name: String,
+        argument: Option<PseudoArgument>,
+    },
+    /// "&", less allows a suffix, eg. "&-item"
+    Nesting {
+        suffix: Option<String>,
+    },
+    /// type, class or id with less interpolation, eg. ".@{prefix}-button"
+    Interpolated {
+        /// '.' for a class, '#' for an id, None for a type
+        kind: Option<char>,
+        /// the name as source text, eg. "@{prefix}-button"
+        name: String,
+        /// interpolated variables, eg. ["prefix"]
+        variables: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AttributeOperator {
+    /// "="
+    Equals,
+    /// "~="
+    Includes,
+    /// "|="
+    DashMatch,
+    /// "^="
+    Prefix,
+    /// "$="
+    Suffix,
+    /// "*="
+    Substring,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PseudoArgument {
+    /// eg. ":not(a, b)", ":has(> img)"
+    Selectors(SelectorList),
+    /// `an+b`, eg. ":nth-child(2n+1 of .item)"
+    Nth {
+        a: i32,
+        b: i32,
+        of: Option<SelectorList>,
+    },
+    /// any other argument as source text, eg. ":lang(en)"
+    Raw(String),
+}
+
+/// pseudo-classes and pseudo-elements with a selector list argument
+const SELECTOR_ARGUMENTS: [&str; 11] = [
+    "not",
+    "is",
+    "where",
+    "has",
+    "matches",
+    "-webkit-any",
+    "-moz-any",
+    "host",
+    "host-context",
+    "slotted",
+    "cue",
+];
+
+/// pseudo-classes with `an+b` argument, "of S" only for the first two
+const NTH_ARGUMENTS: [&str; 6] = [
+    "nth-child",
+    "nth-last-child",
+    "nth-of-type",
+    "nth-last-of-type",
+    "nth-col",
+    "nth-last-col",
+];
+
+/// parse `an+b` without whitespace, eg. "2n+1", "-n+3", "odd"
+fn parse_nth(text: &str) -> Option<(i32, i32)> {
+    let text = text.to_ascii_lowercase();
+    match text.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let (a, b) = match text.find('n') {
+        Some(index) => {
+            let a = match &text[..index] {
+                "" | "+" => 1,
+                "-" => -1,
+                a => a.parse().ok()?,
+            };
+            (a, &text[index + 1..])
+        }
+        None => (0, text.as_str()),
+    };
+    let b = match b {
+        "" => 0,
+        b if a != 0 && !b.starts_with(['+', '-']) => return None,
+        b => b.parse().ok()?,
+    };
+    Some((a, b))
+}
+
+struct SelectorParser<'t> {
+    /// tokens without comments
+    tokens: Vec<&'t Token>,
+    index: usize,
+}
+
+impl<'t> SelectorParser<'t> {
+    fn new(tokens: &'t [Token]) -> SelectorParser<'t> {
+        SelectorParser {
+            tokens: tokens
+                .iter()
+                .filter(|token| !matches!(token, Token::Comment(_, _)))
+                .collect(),
+            index: 0,
+        }
+    }
+
+    fn peek(&self, n: usize) -> Option<&'t Token> {
+        self.tokens.get(self.index + n).copied()
+    }
+
+    fn eat(&mut self, len: usize) {
+        self.index += len;
+    }
+
+    /// skip whitespace, return true if there is any
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while let Some(Token::Whitespace(_, _)) = self.peek(0) {
+            self.eat(1);
+            skipped = true;
+        }
+        skipped
+    }
+
+    fn parse_list(&mut self) -> Option<SelectorList> {
+        let mut list = vec![];
+        loop {
+            self.skip_whitespace();
+            list.push(self.parse_complex()?);
+            match self.peek(0) {
+                Some(Token::Comma(_)) => self.eat(1),
+                None => return Some(list),
+                _ => return None,
+            }
+        }
+    }
+
+    fn parse_combinator(&mut self) -> Option<Combinator> {
+        let combinator = match (self.peek(0), self.peek(1)) {
+            (Some(Token::Delim('>', _)), _) => Combinator::Child,
+            (Some(Token::Delim('+', _)), _) => Combinator::NextSibling,
+            (Some(Token::Delim('~', _)), _) => Combinator::SubsequentSibling,
+            (Some(Token::Delim('|', _)), Some(Token::Delim('|', _))) => {
+                self.eat(1);
+                Combinator::Column
+            }
+            _ => return None,
+        };
+        self.eat(1);
+        Some(combinator)
+    }
+
+    fn parse_complex(&mut self) -> Option<ComplexSelector> {
+        let mut parts = vec![];
+        let mut combinator = self.parse_combinator();
+        loop {
+            self.skip_whitespace();
+            let compound = self.parse_compound()?;
+            parts.push(SelectorPart {
+                combinator,
+                compound,
+            });
+            let whitespace = self.skip_whitespace();
+            combinator = match self.peek(0) {
+                None | Some(Token::Comma(_)) => return Some(ComplexSelector { parts }),
+                _ => match self.parse_combinator() {
+                    Some(combinator) => Some(combinator),
+                    None if whitespace => Some(Combinator::Descendant),
+                    None => return None,
+                },
+            };
+        }
+    }
+
+    /// namespace prefix before "|", but not the column combinator "||" or the "|=" operator
+    fn parse_namespace(&mut self) -> Option<String> {
+        let prefix = match self.peek(0) {
+            Some(Token::Ident(name, _)) => Some(name.value.to_owned()),
+            Some(Token::Delim('*', _)) => Some(String::from("*")),
+            _ => None,
+        };
+        match (prefix, self.peek(1), self.peek(2)) {
+            (Some(prefix), Some(Token::Delim('|', _)), Some(Token::Ident(_, _)))
+            | (Some(prefix), Some(Token::Delim('|', _)), Some(Token::Delim('*', _))) => {
+                self.eat(2);
+                Some(prefix)
+            }
+            (_, _, _) => match (self.peek(0), self.peek(1)) {
+                (Some(Token::Delim('|', _)), Some(Token::Ident(_, _)))
+                | (Some(Token::Delim('|', _)), Some(Token::Delim('*', _))) => {
+                    self.eat(1);
+                    Some(String::new())
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// type, class or id with less interpolation at the current token, eg. ".@{prefix}-button",
+    /// None if there is no interpolation
+    fn parse_interpolated(&mut self) -> Option<SimpleSelector> {
+        let (kind, mut index, mut name) = match self.peek(0)? {
+            Token::Delim(c @ ('.' | '#'), _) => (Some(*c), self.index + 1, String::new()),
+            Token::Hash(hash, _, _) => (Some('#'), self.index + 1, hash.raw.to_owned()),
+            _ => (None, self.index, String::new()),
+        };
+        let mut variables = vec![];
+        loop {
+            match self.tokens.get(index) {
+                Some(Token::Ident(ident, _)) => name.push_str(&ident.raw),
+                Some(token @ Token::Interpolation(variable, _)) => {
+                    name.push_str(&token.raw());
+                    variables.push(variable.value.to_owned());
+                }
+                _ => break,
+            }
+            index += 1;
+        }
+        if variables.is_empty() {
+            return None;
+        }
+        self.index = index;
+        Some(SimpleSelector::Interpolated {
+            kind,
+            name,
+            variables,
+        })
+    }
+
+    fn parse_compound(&mut self) -> Option<Vec<SimpleSelector>> {
+        let mut compound = vec![];
+        let start = self.index;
+        let namespace = self.parse_namespace();
+        if namespace.is_none() {
+            // eg. "@{tag}" or "h@{level}"
+            if let Some(interpolated) = self.parse_interpolated() {
+                compound.push(interpolated);
+            }
+        }
+        match self.peek(0) {
+            _ if !compound.is_empty() => {}
+            Some(Token::Ident(name, _)) => {
+                self.eat(1);
+                compound.push(SimpleSelector::Type {
+                    namespace,
+                    name: name.value.to_owned(),
+                });
+            }
+            Some(Token::Delim('*', _)) => {
+                self.eat(1);
+                compound.push(SimpleSelector::Universal { namespace });
+            }
+            _ if self.index > start => return None,
+            _ => {}
+        }
+        loop {
+            if let Some(interpolated) = self.parse_interpolated() {
+                compound.push(interpolated);
+                continue;
+            }
+            match self.peek(0) {
+                Some(Token::Delim('.', _)) => match self.peek(1) {
+                    Some(Token::Ident(name, _)) => {
+                        self.eat(2);
+                        compound.push(SimpleSelector::Class(name.value.to_owned()));
+                    }
+                    _ => return None,
+                },
+                Some(Token::Hash(name, HashType::Id, _)) => {
+                    self.eat(1);
+                    compound.push(SimpleSelector::Id(name.value.to_owned()));
+                }
+                Some(Token::Delim('&', _)) => {
+                    self.eat(1);
+                    // less interpolation in the suffix, eg. "&-@{name}"
+                    let mut suffix = String::new();
+                    while let (Some(token @ Token::Ident(_, _)), _)
+                    | (Some(token @ Token::Interpolation(_, _)), _)
+                    | (
+                        Some(token @ Token::Delim('-', _)),
+                        Some(Token::Interpolation(_, _)),
+                    ) = (self.peek(0), self.peek(1))
+                    {
+                        suffix.push_str(&token.raw());
+                        self.eat(1);
+                    }
+                    let suffix = if suffix.is_empty() {
+                        None
+                    } else {
+                        Some(suffix)
+                    };
+                    compound.push(SimpleSelector::Nesting { suffix });
+                }
+                Some(Token::OpenSquare(_)) => {
+                    self.eat(1);
+                    compound.push(self.parse_attribute()?);
+                }
+                Some(Token::Colon(_)) => {
+                    self.eat(1);
+                    compound.push(self.parse_pseudo()?);
+                }
+                _ => break,
+            }
+        }
+        if compound.is_empty() {
+            None
+        } else {
+            Some(compound)
+        }
+    }
+
+    /// after "["
+    fn parse_attribute(&mut self) -> Option<SimpleSelector> {
+        self.skip_whitespace();
+        let namespace = self.parse_namespace();
+        let name = match self.peek(0) {
+            Some(Token::Ident(name, _)) => name.value.to_owned(),
+            _ => return None,
+        };
+        self.eat(1);
+        self.skip_whitespace();
+        let operator = match (self.peek(0), self.peek(1)) {
+            (Some(Token::CloseSquare(_)), _) => {
+                self.eat(1);
+                return Some(SimpleSelector::Attribute {
+                    namespace,
+                    name,
+                    operator: None,
+                    value: None,
+                    modifier: None,
+                });
+            }
+            (Some(Token::Delim('=', _)), _) => AttributeOperator::Equals,
+            (Some(Token::Delim(c, _)), Some(Token::Delim('=', _))) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return None,
+                };
+                self.eat(1);
+                operator
+            }
+            _ => return None,
+        };
+        self.eat(1); // eat "="
+        self.skip_whitespace();
+        let value = match self.peek(0) {
+            Some(Token::Ident(value, _)) | Some(Token::String(value, _)) => value.value.to_owned(),
+            _ => return None,
+        };
+        self.eat(1);
+        self.skip_whitespace();
+        let modifier = match self.peek(0) {
+            Some(Token::Ident(flag, _))
+                if flag.value.eq_ignore_ascii_case("i") || flag.value.eq_ignore_ascii_case("s") =>
+            {
+                self.eat(1);
+                self.skip_whitespace();
+                flag.value.to_ascii_lowercase().chars().next()
+            }
+            _ => None,
+        };
+        match self.peek(0) {
+            Some(Token::CloseSquare(_)) => self.eat(1),
+            _ => return None,
+        }
+        Some(SimpleSelector::Attribute {
+            namespace,
+            name,
+            operator: Some(operator),
+            value: Some(value),
+            modifier,
+        })
+    }
+
+    /// after ":"
+    fn parse_pseudo(&mut self) -> Option<SimpleSelector> {
+        let element = if let Some(Token::Colon(_)) = self.peek(0) {
+            self.eat(1);
+            true
+        } else {
+            false
+        };
+        let (name, argument) = match self.peek(0) {
+            Some(Token::Ident(name, _)) => {
+                self.eat(1);
+                (name.value.to_owned(), None)
+            }
+            Some(Token::Function(name, _)) => {
+                self.eat(1);
+                let from = self.index;
+                let mut depth = 0;
+                loop {
+                    match self.peek(0)? {
+                        Token::CloseParen(_) if depth == 0 => break,
+                        Token::CloseParen(_) => depth -= 1,
+                        Token::OpenParen(_) | Token::Function(_, _) => depth += 1,
+                        _ => {}
+                    }
+                    self.eat(1);
+                }
+                let tokens = self.tokens[from..self.index].to_vec();
+                self.eat(1); // eat ")"
+                let argument = Self::parse_argument(&name.value.to_ascii_lowercase(), &tokens)?;
+                (name.value.to_owned(), Some(argument))
+            }
+            _ => return None,
+        };
+        if element {
+            Some(SimpleSelector::PseudoElement { name, argument })
+        } else {
+            Some(SimpleSelector::PseudoClass { name, argument })
+        }
+    }
+
+    fn parse_argument(name: &str, tokens: &[&'t Token]) -> Option<PseudoArgument> {
+        let sub_parser = |tokens: &[&'t Token]| -> Option<SelectorList> {
+            let mut parser = SelectorParser {
+                tokens: tokens.to_vec(),
+                index: 0,
+            };
+            parser.parse_list()
+        };
+        if SELECTOR_ARGUMENTS.contains(&name) {
+            return Some(PseudoArgument::Selectors(sub_parser(tokens)?));
+        }
+        if NTH_ARGUMENTS.contains(&name) {
+            let of = tokens
+                .iter()
+                .position(|token| matches!(token, Token::Ident(ident, _) if ident.value.eq_ignore_ascii_case("of")));
+            let (nth, of) = match of {
+                Some(index) if name == "nth-child" || name == "nth-last-child" => {
+                    (&tokens[..index], Some(sub_parser(&tokens[index + 1..])?))
+                }
+                Some(_) => return None,
+                None => (tokens, None),
+            };
+            let text: String = nth
+                .iter()
+                .filter(|token| !matches!(token, Token::Whitespace(_, _)))
+                .map(|token| token.raw())
+                .collect();
+            let (a, b) = parse_nth(&text)?;
+            return Some(PseudoArgument::Nth { a, b, of });
+        }
+        let raw: String = tokens.iter().map(|token| token.raw()).collect();
+        Some(PseudoArgument::Raw(raw.trim().to_owned()))
+    }
+}
+
+/// parse selector list from tokens, None if it's not a valid selector,
+/// eg. a less mixin definition or a keyframe selector
+pub fn parse_selector_list(tokens: &[Token]) -> Option<SelectorList> {
+    let mut parser = SelectorParser::new(tokens);
+    let list = parser.parse_list()?;
+    Some(list)
+}
@@ -0,0 +1,233 @@
+use super::media::matching_paren;
+use super::selector::{parse_selector_list, PseudoArgument, SelectorList, SimpleSelector};
+use crate::lexer::Token;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// condition of "@supports", <https://www.w3.org/TR/css-conditional-4/#at-supports>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SupportsCondition {
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// eg. "(display: grid)", the value as source text
+    Declaration {
+        prop: String,
+        value: String,
+    },
+    /// eg. "selector(a > b)", the selector as source text
+    Selector(String),
+    /// eg. "font-tech(color-COLRv1)"
+    FontTech(String),
+    /// eg. "font-format(woff2)"
+    FontFormat(String),
+    /// `<general-enclosed>` as source text, always false
+    GeneralEnclosed(String),
+}
+
+/// what a browser supports, to evaluate `SupportsCondition`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SupportedFeatures {
+    /// lowercase props with the values supported, an empty list for any value
+    pub declarations: HashMap<String, Vec<String>>,
+    /// lowercase names of pseudo-classes and pseudo-elements supported in "selector()",
+    /// None for any valid selector
+    pub pseudos: Option<Vec<String>>,
+    pub font_techs: Vec<String>,
+    pub font_formats: Vec<String>,
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Ident(ident, _)) if ident.value.eq_ignore_ascii_case(keyword))
+}
+
+fn text_of(tokens: &[&Token]) -> String {
+    let text: String = tokens.iter().map(|token| token.raw()).collect();
+    text.trim().to_owned()
+}
+
+struct SupportsParser<'t> {
+    /// tokens without comments
+    tokens: Vec<&'t Token>,
+    index: usize,
+}
+
+impl<'t> SupportsParser<'t> {
+    fn new(tokens: &[&'t Token]) -> SupportsParser<'t> {
+        SupportsParser {
+            tokens: tokens
+                .iter()
+                .filter(|token| !matches!(token, Token::Comment(_, _)))
+                .copied()
+                .collect(),
+            index: 0,
+        }
+    }
+
+    /// the next token after whitespace
+    fn peek(&mut self) -> Option<&'t Token> {
+        while let Some(Token::Whitespace(_, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
+        self.tokens.get(self.index).copied()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn parse_condition(&mut self) -> Option<SupportsCondition> {
+        if is_keyword(self.peek(), "not") {
+            self.index += 1;
+            return Some(SupportsCondition::Not(Box::new(self.parse_in_parens()?)));
+        }
+        let mut conditions = vec![self.parse_in_parens()?];
+        let mut keyword: Option<String> = None;
+        while let Some(Token::Ident(ident, _)) = self.peek() {
+            let next = ident.value.to_ascii_lowercase();
+            match &keyword {
+                // "and" and "or" can't be mixed without parens
+                Some(keyword) if *keyword != next => return None,
+                _ if next == "and" || next == "or" => {}
+                _ => return None,
+            }
+            self.index += 1;
+            conditions.push(self.parse_in_parens()?);
+            keyword = Some(next);
+        }
+        match keyword.as_deref() {
+            Some("and") => Some(SupportsCondition::And(conditions)),
+            Some(_) => Some(SupportsCondition::Or(conditions)),
+            None => conditions.pop(),
+        }
+    }
+
+    fn parse_in_parens(&mut self) -> Option<SupportsCondition> {
+        let name = match self.peek()? {
+            Token::OpenParen(_) => None,
+            Token::Function(name, _) => Some(name.value.to_ascii_lowercase()),
+            _ => return None,
+        };
+        let start = self.index;
+        let close = matching_paren(&self.tokens, start)?;
+        self.index = close + 1;
+        let inner = &self.tokens[start + 1..close];
+        let general = SupportsCondition::GeneralEnclosed(text_of(&self.tokens[start..=close]));
+        let condition = match name.as_deref() {
+            Some("selector") => SupportsCondition::Selector(text_of(inner)),
+            Some("font-tech") => SupportsCondition::FontTech(text_of(inner)),
+            Some("font-format") => SupportsCondition::FontFormat(text_of(inner)),
+            Some(_) => general,
+            None => {
+                let mut parser = SupportsParser::new(inner);
+                match parser.parse_condition() {
+                    Some(condition) if parser.at_end() => condition,
+                    _ => parse_declaration(inner).unwrap_or(general),
+                }
+            }
+        };
+        Some(condition)
+    }
+}
+
+/// "prop: value" of "(prop: value)", or of "supports(prop: value)" of "@import"
+pub(super) fn parse_declaration(tokens: &[&Token]) -> Option<SupportsCondition> {
+    let mut parser = SupportsParser::new(tokens);
+    let prop = match parser.peek()? {
+        Token::Ident(ident, _) => ident.value.to_owned(),
+        _ => return None,
+    };
+    parser.index += 1;
+    match parser.peek()? {
+        Token::Colon(_) => parser.index += 1,
+        _ => return None,
+    }
+    let value = text_of(&parser.tokens[parser.index..]);
+    if value.is_empty() {
+        return None;
+    }
+    Some(SupportsCondition::Declaration { prop, value })
+}
+
+/// parse the condition of "@supports"
+pub fn parse_supports_condition(tokens: &[&Token]) -> Option<SupportsCondition> {
+    let mut parser = SupportsParser::new(tokens);
+    let condition = parser.parse_condition()?;
+    if parser.at_end() {
+        Some(condition)
+    } else {
+        None
+    }
+}
+
+/// names of pseudo-classes and pseudo-elements in a selector list, with the nested ones
+fn pseudo_names(list: &SelectorList, names: &mut Vec<String>) {
+    let compounds = list
+        .iter()
+        .flat_map(|complex| complex.parts.iter())
+        .flat_map(|part| part.compound.iter());
+    for simple in compounds {
+        let (name, argument) = match simple {
+            SimpleSelector::PseudoClass { name, argument }
+            | SimpleSelector::PseudoElement { name, argument } => (name, argument),
+            _ => continue,
+        };
+        names.push(name.to_ascii_lowercase());
+        match argument {
+            Some(PseudoArgument::Selectors(list))
+            | Some(PseudoArgument::Nth { of: Some(list), .. }) => pseudo_names(list, names),
+            _ => {}
+        }
+    }
+}
+
+impl SupportsCondition {
+    /// whether a browser with the features supports the condition
+    pub fn matches(&self, features: &SupportedFeatures) -> bool {
+        match self {
+            SupportsCondition::Not(condition) => !condition.matches(features),
+            SupportsCondition::And(conditions) => conditions
+                .iter()
+                .all(|condition| condition.matches(features)),
+            SupportsCondition::Or(conditions) => conditions
+                .iter()
+                .any(|condition| condition.matches(features)),
+            SupportsCondition::Declaration { prop, value } => {
+                match features.declarations.get(&prop.to_ascii_lowercase()) {
+                    Some(values) => {
+                        values.is_empty()
+                            || values
+                                .iter()
+                                .any(|supported| supported.eq_ignore_ascii_case(value))
+                    }
+                    None => false,
+                }
+            }
+            SupportsCondition::Selector(selector) => {
+                let tokens = crate::lexer::Lexer::new().lex_from_source(selector);
+                let list = match parse_selector_list(&tokens) {
+                    Some(list) => list,
+                    None => return false,
+                };
+                match &features.pseudos {
+                    Some(pseudos) => {
+                        let mut names = vec![];
+                        pseudo_names(&list, &mut names);
+                        names.iter().all(|name| pseudos.contains(name))
+                    }
+                    None => true,
+                }
+            }
+            SupportsCondition::FontTech(tech) => features
+                .font_techs
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(tech)),
+            SupportsCondition::FontFormat(format) => features
+                .font_formats
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(format)),
+            SupportsCondition::GeneralEnclosed(_) => false,
+        }
+    }
+}
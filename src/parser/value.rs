@@ -0,0 +1,178 @@
+use crate::lexer::{Numeric, Token};
+use serde::{Deserialize, Serialize};
+
+/// component values of a declaration value, <https://www.w3.org/TR/css-syntax-3/#component-value>
+///
+/// comments are dropped, whitespace is collapsed to one `Whitespace` and dropped
+/// around separators and at the start and end of a list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ComponentValue {
+    Ident(String),
+    Number(Numeric),
+    /// the number without "%"
+    Percentage(Numeric),
+    Dimension {
+        number: Numeric,
+        unit: String,
+    },
+    /// the value without quotes
+    String(String),
+    /// the value of "url(...)", a "url()" with a quoted string is a `Function`
+    Url(String),
+    /// hex color without "#", eg. "fff" of "#fff"
+    Color(String),
+    /// any other hash without "#", eg. "#main"
+    Hash(String),
+    /// less variable, eg. "@color"
+    AtKeyword(String),
+    /// less interpolation, eg. "prefix" of "@{prefix}"
+    Interpolation(String),
+    /// less map lookup, eg. "@config[primary]", the keys as source text
+    Lookup {
+        variable: String,
+        keys: Vec<String>,
+    },
+    Function {
+        name: String,
+        arguments: Vec<ComponentValue>,
+    },
+    /// "(...)", "[...]" or "{...}"
+    Block {
+        open: char,
+        values: Vec<ComponentValue>,
+    },
+    Comma,
+    Slash,
+    Whitespace,
+    /// any other single character, eg. "+" or "!"
+    Delim(char),
+    /// bad string or url as source text
+    Bad(String),
+}
+
+fn is_hex_color(name: &str) -> bool {
+    [3, 4, 6, 8].contains(&name.len()) && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_separator(value: &ComponentValue) -> bool {
+    matches!(value, ComponentValue::Comma | ComponentValue::Slash)
+}
+
+struct ValueParser<'t> {
+    /// tokens without comments
+    tokens: Vec<&'t Token>,
+    index: usize,
+}
+
+impl<'t> ValueParser<'t> {
+    fn peek(&self) -> Option<&'t Token> {
+        self.tokens.get(self.index).copied()
+    }
+
+    /// keys of a less map lookup, eg. "[@dark][primary]"
+    fn parse_keys(&mut self) -> Vec<String> {
+        let mut keys = vec![];
+        while let Some(Token::OpenSquare(_)) = self.peek() {
+            self.index += 1;
+            let mut key = String::new();
+            while let Some(token) = self.peek() {
+                self.index += 1;
+                match token {
+                    Token::CloseSquare(_) => break,
+                    token => key.push_str(&token.raw()),
+                }
+            }
+            keys.push(key.trim().to_owned());
+        }
+        keys
+    }
+
+    /// parse values until the end of tokens, or the closing token of a block
+    fn parse_list(&mut self, close: Option<char>) -> Vec<ComponentValue> {
+        let mut values: Vec<ComponentValue> = vec![];
+        let mut space = false;
+        while let Some(token) = self.peek() {
+            self.index += 1;
+            let value = match token {
+                Token::Whitespace(_, _) => {
+                    space = true;
+                    continue;
+                }
+                Token::CloseParen(_) if close == Some(')') => break,
+                Token::CloseSquare(_) if close == Some(']') => break,
+                Token::CloseCurly(_) if close == Some('}') => break,
+                Token::Ident(text, _) => ComponentValue::Ident(text.value.to_owned()),
+                Token::Number(number, _) => ComponentValue::Number(number.to_owned()),
+                Token::Percentage(number, _) => ComponentValue::Percentage(number.to_owned()),
+                Token::Dimension(number, unit, _) => ComponentValue::Dimension {
+                    number: number.to_owned(),
+                    unit: unit.value.to_owned(),
+                },
+                Token::String(text, _) => ComponentValue::String(text.value.to_owned()),
+                Token::Url(text, _) => ComponentValue::Url(text.value.to_owned()),
+                Token::Hash(name, _, _) if is_hex_color(&name.raw) => {
+                    ComponentValue::Color(name.value.to_owned())
+                }
+                Token::Hash(name, _, _) => ComponentValue::Hash(name.value.to_owned()),
+                Token::AtKeyword(name, _) if matches!(self.peek(), Some(Token::OpenSquare(_))) => {
+                    ComponentValue::Lookup {
+                        variable: name.value.to_owned(),
+                        keys: self.parse_keys(),
+                    }
+                }
+                Token::AtKeyword(name, _) => ComponentValue::AtKeyword(name.value.to_owned()),
+                Token::Interpolation(name, _) => {
+                    ComponentValue::Interpolation(name.value.to_owned())
+                }
+                Token::Function(name, _) => ComponentValue::Function {
+                    name: name.value.to_owned(),
+                    arguments: self.parse_list(Some(')')),
+                },
+                Token::OpenParen(_) => ComponentValue::Block {
+                    open: '(',
+                    values: self.parse_list(Some(')')),
+                },
+                Token::OpenSquare(_) => ComponentValue::Block {
+                    open: '[',
+                    values: self.parse_list(Some(']')),
+                },
+                Token::OpenCurly(_) => ComponentValue::Block {
+                    open: '{',
+                    values: self.parse_list(Some('}')),
+                },
+                Token::Comma(_) => ComponentValue::Comma,
+                Token::Delim('/', _) => ComponentValue::Slash,
+                Token::Delim(c, _) => ComponentValue::Delim(*c),
+                Token::BadString(string, _) | Token::BadUrl(string, _) => {
+                    ComponentValue::Bad(string.to_owned())
+                }
+                // a close token without its open token, "<!--", "-->", ":" or ";"
+                token => match token.raw().chars().next() {
+                    Some(c) if token.raw().len() == 1 => ComponentValue::Delim(c),
+                    _ => ComponentValue::Bad(token.raw()),
+                },
+            };
+            if space
+                && !is_separator(&value)
+                && matches!(values.last(), Some(last) if !is_separator(last))
+            {
+                values.push(ComponentValue::Whitespace);
+            }
+            space = false;
+            values.push(value);
+        }
+        values
+    }
+}
+
+/// parse component values of a declaration value from its tokens, without "!important"
+pub fn parse_component_values(tokens: &[Token]) -> Vec<ComponentValue> {
+    let mut parser = ValueParser {
+        tokens: tokens
+            .iter()
+            .filter(|token| !matches!(token, Token::Comment(_, _)))
+            .collect(),
+        index: 0,
+    };
+    parser.parse_list(None)
+}
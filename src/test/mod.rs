@@ -1,7 +1,18 @@
 #[cfg(test)]
 mod tests {
+    use crate::compiler;
+    use crate::formatter;
+    use crate::generator;
     use crate::lexer;
+    use crate::lexer::{HashType, NumberType, Token};
+    use crate::minifier;
     use crate::parser;
+    use crate::parser::{
+        AtrulePrelude, AttributeOperator, Combinator, ComponentValue, Environment, Guard,
+        GuardEvaluator, MediaCondition, MediaFeature, MediaModifier, MediaValue, MixinArgument,
+        NodeType, PageSelector, ParseErrorKind, PseudoArgument, RangeOperator, RawValue, Raws,
+        SimpleSelector, SupportedFeatures, SupportsCondition,
+    };
     #[test]
     fn read_str() {
         let source = r###"@variable: #999;
@@ -61,8 +72,2212 @@ mod tests {
         }
     }"###;
 
-        let tokens = lexer::Lexer::new().lex_from_source(&source.to_owned());
+        let tokens = lexer::Lexer::new().lex_from_source(source);
         let mut parser = parser::Parser::new(&tokens);
-        parser.parse();
+        let result = parser.parse();
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn read_comments() {
+        let source = r###"/*
+ * license header
+ */
+// less inline comment
+main /* selector note */ {
+    /* inside rule */
+    color: white; // trailing note
+    background: url(//cdn.example.com/a.png);
+    @media screen {
+        /* nested */
+        left: 0;
+    }
+}"###;
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let mut parser = parser::Parser::new(&tokens);
+        let nodes = parser.parse().nodes;
+        let texts = |nodes: &Vec<NodeType>| -> Vec<String> {
+            nodes
+                .iter()
+                .filter_map(|node| match node {
+                    NodeType::Comment { text, .. } => Some(text.to_owned()),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            texts(&nodes),
+            vec!["* license header", "less inline comment"]
+        );
+        match &nodes[2] {
+            NodeType::Rule {
+                selector, nodes, ..
+            } => {
+                assert_eq!(selector, "main");
+                assert_eq!(texts(nodes), vec!["inside rule", "trailing note"]);
+                match &nodes[3] {
+                    NodeType::Decl { prop, value, .. } => {
+                        assert_eq!(prop, "background");
+                        assert_eq!(value, "url(//cdn.example.com/a.png)");
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+                match &nodes[4] {
+                    NodeType::Atrule { nodes, .. } => {
+                        assert_eq!(texts(nodes.as_ref().unwrap()), vec!["nested"]);
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn read_locations() {
+        let source = "@color: red;\r\nmain {\n    font-size: 3em;\n    @media print {\n        left: 0\n    }\n}";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let mut parser = parser::Parser::new(&tokens);
+        let nodes = parser.parse().nodes;
+        let span = |node: &NodeType| -> (usize, usize, usize, usize, usize, usize) {
+            let source = match node {
+                NodeType::Rule { source, .. }
+                | NodeType::Keyframe { source, .. }
+                | NodeType::MixinDefinition { source, .. }
+                | NodeType::MixinCall { source, .. }
+                | NodeType::Atrule { source, .. }
+                | NodeType::Decl { source, .. }
+                | NodeType::VariableDecl { source, .. }
+                | NodeType::Comment { source, .. } => source,
+                NodeType::Root => unreachable!(),
+            };
+            (
+                source.start.line,
+                source.start.column,
+                source.start.offset,
+                source.end.line,
+                source.end.column,
+                source.end.offset,
+            )
+        };
+        assert_eq!(span(&nodes[0]), (0, 0, 0, 0, 12, 12));
+        assert_eq!(span(&nodes[1]), (1, 0, 14, 6, 1, 83));
+        match &nodes[1] {
+            NodeType::Rule { nodes, .. } => {
+                assert_eq!(span(&nodes[0]), (2, 4, 25, 2, 19, 40));
+                assert_eq!(span(&nodes[1]), (3, 4, 45, 5, 5, 81));
+                match &nodes[1] {
+                    NodeType::Atrule { nodes, .. } => {
+                        assert_eq!(span(&nodes.as_ref().unwrap()[0]), (4, 8, 68, 4, 15, 75));
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        assert_eq!(&source[25..40], "font-size: 3em;");
+    }
+
+    #[test]
+    fn read_errors() {
+        let source = r###"main {
+    color red;
+    font-size: 3em;
+    : 10px;
+    h3 {
+        left: 0;
+    }
+}
+@;
+@import;
+}
+.after-error {
+    right: 0
+}"###;
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        let kinds: Vec<ParseErrorKind> = result.errors.iter().map(|e| e.kind.to_owned()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ParseErrorKind::MissingColon,
+                ParseErrorKind::MissingProp,
+                ParseErrorKind::MissingAtruleName,
+                ParseErrorKind::EmptyAtruleParams,
+                ParseErrorKind::UnexpectedToken,
+            ]
+        );
+        assert_eq!(result.errors[0].span.start.line, 1);
+        assert_eq!(result.errors[0].span.start.column, 4);
+        assert_eq!(result.nodes.len(), 2);
+        match &result.nodes[0] {
+            NodeType::Rule { nodes, .. } => {
+                assert_eq!(nodes.len(), 2);
+                match &nodes[0] {
+                    NodeType::Decl { prop, value, .. } => {
+                        assert_eq!(prop, "font-size");
+                        assert_eq!(value, "3em");
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        match &result.nodes[1] {
+            NodeType::Rule {
+                selector, nodes, ..
+            } => {
+                assert_eq!(selector, ".after-error");
+                assert_eq!(nodes.len(), 1);
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+
+        let tokens = lexer::Lexer::new().lex_from_source("a { color red; } b { left: 0; }");
+        let error = parser::Parser::new(&tokens).parse_strict().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::MissingColon);
+        let tokens = lexer::Lexer::new().lex_from_source("a { b { left: 0; }");
+        let error = parser::Parser::new(&tokens).parse_strict().unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnclosedBlock);
+    }
+
+    #[test]
+    fn read_multiline() {
+        let source = ".grid {\n    grid-template-areas:\n        \"header header\"\n        \"sidebar main\";\n    font-family: Helvetica,\n        Arial, sans-serif;\n    content: \"a \\\n b\";\n    /* a\n comment */ color: red }";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        match &result.nodes[0] {
+            NodeType::Rule { nodes, .. } => {
+                let decls: Vec<(String, String)> = nodes
+                    .iter()
+                    .filter_map(|node| match node {
+                        NodeType::Decl { prop, value, .. } => {
+                            Some((prop.to_owned(), value.to_owned()))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(
+                    decls,
+                    vec![
+                        (
+                            "grid-template-areas".to_owned(),
+                            "\"header header\"\n        \"sidebar main\"".to_owned()
+                        ),
+                        (
+                            "font-family".to_owned(),
+                            "Helvetica,\n        Arial, sans-serif".to_owned()
+                        ),
+                        ("content".to_owned(), "\"a \\\n b\"".to_owned()),
+                        ("color".to_owned(), "red".to_owned()),
+                    ]
+                );
+                match &nodes[3] {
+                    NodeType::Comment { text, source, .. } => {
+                        assert_eq!(text, "a\n comment");
+                        assert_eq!(source.end.line, 9);
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn read_tokens() {
+        let source =
+            "<!-- @media #main.a:not(#999){b:url( x.png )10px 5% -.5e2 \"s\" 'bad\n}\r\n-->";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let kinds: Vec<String> = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Ident(string, _) => format!("ident {}", string.raw),
+                Token::Function(string, _) => format!("function {}", string.raw),
+                Token::AtKeyword(string, _) => format!("at-keyword {}", string.raw),
+                Token::Hash(string, HashType::Id, _) => format!("hash id {}", string.raw),
+                Token::Hash(string, HashType::Unrestricted, _) => format!("hash {}", string.raw),
+                Token::String(string, _) => format!("string {}", string.raw),
+                Token::BadString(string, _) => format!("bad-string {}", string),
+                Token::Url(string, _) => format!("url {}", string.raw),
+                Token::Delim(c, _) => format!("delim {}", c),
+                Token::Number(number, _) => format!("number {}", number.repr),
+                Token::Percentage(number, _) => format!("percentage {}", number.repr),
+                Token::Dimension(number, unit, _) => {
+                    format!("dimension {} {}", number.repr, unit.raw)
+                }
+                Token::Whitespace(_, _) => String::from("whitespace"),
+                token => token.raw(),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "<!--",
+                "whitespace",
+                "at-keyword media",
+                "whitespace",
+                "hash id main",
+                "delim .",
+                "ident a",
+                ":",
+                "function not",
+                "hash 999",
+                ")",
+                "{",
+                "ident b",
+                ":",
+                "url url( x.png )",
+                "dimension 10 px",
+                "whitespace",
+                "percentage 5",
+                "whitespace",
+                "number -.5e2",
+                "whitespace",
+                "string \"s\"",
+                "whitespace",
+                "bad-string 'bad",
+                "whitespace",
+                "}",
+                "whitespace",
+                "-->",
+            ]
+        );
+        let raw: String = tokens.iter().map(|token| token.raw()).collect();
+        assert_eq!(raw, source);
+        let cdc = tokens.last().unwrap().location();
+        assert_eq!(
+            (cdc.start.line, cdc.start.column, cdc.start.offset),
+            (2, 0, 70)
+        );
+    }
+
+    #[test]
+    fn read_numbers() {
+        let source = "0.5em .25 -10px +3 1e3 1.5s 50% -0 2n+1 1E-2";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let numbers: Vec<(f64, NumberType, Option<char>, String)> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Number(number, _) => Some((number, String::new())),
+                Token::Percentage(number, _) => Some((number, String::from("%"))),
+                Token::Dimension(number, unit, _) => Some((number, unit.value.to_owned())),
+                _ => None,
+            })
+            .map(|(number, unit)| {
+                (
+                    number.value,
+                    number.number_type.to_owned(),
+                    number.sign,
+                    unit,
+                )
+            })
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                (0.5, NumberType::Number, None, "em".to_owned()),
+                (0.25, NumberType::Number, None, "".to_owned()),
+                (-10.0, NumberType::Integer, Some('-'), "px".to_owned()),
+                (3.0, NumberType::Integer, Some('+'), "".to_owned()),
+                (1000.0, NumberType::Number, None, "".to_owned()),
+                (1.5, NumberType::Number, None, "s".to_owned()),
+                (50.0, NumberType::Integer, None, "%".to_owned()),
+                (0.0, NumberType::Integer, Some('-'), "".to_owned()),
+                (2.0, NumberType::Integer, None, "n".to_owned()),
+                (1.0, NumberType::Integer, Some('+'), "".to_owned()),
+                (0.01, NumberType::Number, None, "".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_escapes() {
+        let source = ".日本語, .\\31 0, .a\\:hover, .--_private, .__bem__elem, .🎉 { content: \"\\2192 \\\"x\"; background: url( a\\)b.png ) }";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let texts: Vec<(String, String)> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Ident(text, _) | Token::String(text, _) | Token::Url(text, _) => {
+                    Some((text.value.to_owned(), text.raw.to_owned()))
+                }
+                _ => None,
+            })
+            .collect();
+        let pair = |value: &str, raw: &str| (value.to_owned(), raw.to_owned());
+        assert_eq!(
+            texts,
+            vec![
+                pair("日本語", "日本語"),
+                pair("10", "\\31 0"),
+                pair("a:hover", "a\\:hover"),
+                pair("--_private", "--_private"),
+                pair("__bem__elem", "__bem__elem"),
+                pair("🎉", "🎉"),
+                pair("content", "content"),
+                pair("→\"x", "\"\\2192 \\\"x\""),
+                pair("background", "background"),
+                pair("a)b.png", "url( a\\)b.png )"),
+            ]
+        );
+
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        match &result.nodes[0] {
+            NodeType::Rule { selector, .. } => assert_eq!(
+                selector,
+                ".日本語, .\\31 0, .a\\:hover, .--_private, .__bem__elem, .🎉"
+            ),
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn read_unicode_positions() {
+        let source = "/* 中文 */ a{content:\"→😀\";b:1}";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let semicolon = tokens
+            .iter()
+            .find(|token| matches!(token, Token::Semicolon(_)))
+            .unwrap()
+            .location();
+        assert_eq!(semicolon.start.offset, source.find(';').unwrap());
+        assert_eq!(semicolon.start.column, 32);
+        assert_eq!(semicolon.start.column_utf16, 24);
+        assert_eq!(semicolon.start.line, 0);
+
+        // never panic on arbitrary input, including every truncation of a source
+        let samples = vec![
+            "\\",
+            "#",
+            "@",
+            "url(",
+            "url( a b",
+            "'",
+            "\"\\",
+            "/*",
+            "\0a\0{\0:\0}",
+            "\r\r\n\x0C",
+            "-",
+            "+.",
+            "-->",
+            "<!-",
+            "}}}{{{",
+            "a{b:c!}",
+            "@media (",
+            "é→😀\\😀",
+            "a:b:c:d",
+        ];
+        let full = "@media (a:1){.x\\:y→:not(#中){b:url(//é) !important;c:\"😀\\\n\"}}/*é";
+        let mut sources: Vec<&str> = samples;
+        for (index, _) in full.char_indices() {
+            sources.push(&full[..index]);
+        }
+        for source in sources {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            let raw: String = tokens.iter().map(|token| token.raw()).collect();
+            assert_eq!(raw, source);
+            parser::Parser::new(&tokens).parse();
+        }
+    }
+
+    #[test]
+    fn read_raws() {
+        let source = "@v:  2px ;\n@charset \"utf-8\" ;\na /* x */ ,b  {\n  color : red /* c */ ;\n  margin:0 ! important\n}\n@media  screen{ b { top: 1px;; } }\n/*  note */\n";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        let raws = |node: &NodeType| -> Raws {
+            match node {
+                NodeType::Rule { raws, .. }
+                | NodeType::Keyframe { raws, .. }
+                | NodeType::MixinDefinition { raws, .. }
+                | NodeType::MixinCall { raws, .. }
+                | NodeType::Atrule { raws, .. }
+                | NodeType::Decl { raws, .. }
+                | NodeType::VariableDecl { raws, .. }
+                | NodeType::Comment { raws, .. } => raws.to_owned(),
+                NodeType::Root => unreachable!(),
+            }
+        };
+        let text = |s: &str| Some(s.to_owned());
+        let raw_value = |value: &str, raw: &str| {
+            Some(RawValue {
+                value: value.to_owned(),
+                raw: raw.to_owned(),
+            })
+        };
+        let nodes = &result.nodes;
+        assert_eq!(
+            raws(&nodes[0]),
+            Raws {
+                before: text(""),
+                between: text(":  "),
+                value: raw_value("2px", "2px "),
+                ..Raws::default()
+            }
+        );
+        assert_eq!(
+            raws(&nodes[1]),
+            Raws {
+                before: text("\n"),
+                between: text(" "),
+                after_name: text(" "),
+                ..Raws::default()
+            }
+        );
+        assert_eq!(
+            raws(&nodes[2]),
+            Raws {
+                before: text("\n"),
+                between: text("  "),
+                after: text("\n"),
+                semicolon: Some(false),
+                selector: raw_value("a  ,b", "a /* x */ ,b"),
+                ..Raws::default()
+            }
+        );
+        match &nodes[2] {
+            NodeType::Rule {
+                selector, nodes, ..
+            } => {
+                assert_eq!(selector, "a  ,b");
+                assert_eq!(
+                    raws(&nodes[0]),
+                    Raws {
+                        before: text("\n  "),
+                        between: text(" : "),
+                        value: raw_value("red", "red /* c */ "),
+                        ..Raws::default()
+                    }
+                );
+                assert_eq!(
+                    raws(&nodes[1]),
+                    Raws {
+                        before: text("\n  "),
+                        between: text(":"),
+                        important: text(" ! important"),
+                        ..Raws::default()
+                    }
+                );
+                match &nodes[1] {
+                    NodeType::Decl {
+                        value, important, ..
+                    } => {
+                        assert_eq!(value, "0");
+                        assert_eq!(important, &Some(true));
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        assert_eq!(
+            raws(&nodes[3]),
+            Raws {
+                before: text("\n"),
+                between: text(""),
+                after: text(" "),
+                semicolon: Some(false),
+                after_name: text("  "),
+                ..Raws::default()
+            }
+        );
+        match &nodes[3] {
+            NodeType::Atrule { nodes, .. } => {
+                let rule = &nodes.as_ref().unwrap()[0];
+                assert_eq!(
+                    raws(rule),
+                    Raws {
+                        before: text(" "),
+                        between: text(" "),
+                        after: text("; "),
+                        semicolon: Some(true),
+                        ..Raws::default()
+                    }
+                );
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        assert_eq!(
+            raws(&nodes[4]),
+            Raws {
+                before: text("\n"),
+                left: text("  "),
+                right: text(" "),
+                ..Raws::default()
+            }
+        );
+        assert_eq!(result.raws.after, text("\n"));
+        assert_eq!(result.raws.semicolon, Some(false));
+    }
+
+    #[test]
+    fn write_nodes() {
+        let sources = vec![
+            include_str!("../../test/test.less"),
+            "@v:  2px ;\n@charset \"utf-8\" ;\na /* x */ ,b  {\n  color : red /* c */ ;\n  margin:0 ! important\n}\n@media  screen{ b { top: 1px;; } }\n/*  note */\n",
+            "a{b:c}// end",
+            "\r\n<!-- a { b: c } -->\n",
+            "@starting-style {}\n@page :first{margin:1in}",
+            "a { color: red\n\n}\n\n",
+        ];
+        let options = generator::Options::default();
+        for source in sources {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            let result = parser::Parser::new(&tokens).parse();
+            assert!(result.errors.is_empty());
+            let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+            assert_eq!(output, source);
+        }
+
+        let source =
+            "@color: red;\n@import url(a.css);\n@media screen{a,b{color:red!important;// note\n}}";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let nodes = parser::Parser::new(&tokens).parse().nodes;
+        let options = generator::Options {
+            ignore_raws: true,
+            indent: String::from("  "),
+            ..generator::Options::default()
+        };
+        assert_eq!(
+            generator::stringify(&nodes, &options),
+            "@color: red;\n@import url(a.css);\n@media screen {\n  a,b {\n    color: red !important;\n    // note\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn write_source_map() {
+        let source = "a{color:red}\nb{top:0}";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        let options = generator::Options {
+            ignore_raws: true,
+            indent: String::from("  "),
+            map: Some(generator::MapOptions {
+                from: Some(String::from("a.css")),
+                to: Some(String::from("a.out.css")),
+                content: Some(source.to_owned()),
+                ..generator::MapOptions::default()
+            }),
+        };
+        let mut generator = generator::Generator::new(&options);
+        let output = generator.generate(&result.nodes, &result.raws);
+        assert_eq!(output, "a {\n  color: red;\n}\nb {\n  top: 0;\n}");
+        let map = generator.source_map().unwrap();
+        assert_eq!(map.file, Some(String::from("a.out.css")));
+        assert_eq!(map.sources, vec!["a.css"]);
+        assert_eq!(map.sources_content, Some(vec![Some(source.to_owned())]));
+        let positions: Vec<(usize, usize, usize, usize)> = map
+            .decode()
+            .iter()
+            .map(|m| {
+                (
+                    m.generated_line,
+                    m.generated_column,
+                    m.original_line,
+                    m.original_column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, 0, 0, 0),
+                (1, 2, 0, 2),
+                (1, 13, 0, 11),
+                (2, 1, 0, 12),
+                (3, 0, 1, 0),
+                (4, 2, 1, 2),
+                (4, 9, 1, 7),
+                (5, 1, 1, 8),
+            ]
+        );
+        assert!(map.to_json().starts_with("{\"version\":3,"));
+
+        // chain a source map of the input
+        let prev = generator::SourceMap::new(
+            None,
+            vec![String::from("a.less")],
+            None,
+            &[
+                generator::Mapping {
+                    generated_line: 0,
+                    generated_column: 0,
+                    source: 0,
+                    original_line: 2,
+                    original_column: 4,
+                },
+                generator::Mapping {
+                    generated_line: 1,
+                    generated_column: 2,
+                    source: 0,
+                    original_line: 5,
+                    original_column: 0,
+                },
+            ],
+        );
+        let options = generator::Options {
+            map: Some(generator::MapOptions {
+                inline: true,
+                prev: Some(prev),
+                ..generator::MapOptions::default()
+            }),
+            ..generator::Options::default()
+        };
+        let mut generator = generator::Generator::new(&options);
+        let output = generator.generate(&result.nodes, &result.raws);
+        assert!(output.starts_with(
+            "a{color:red}\nb{top:0}\n/*# sourceMappingURL=data:application/json;base64,"
+        ));
+        let map = generator.source_map().unwrap();
+        assert_eq!(map.sources, vec!["a.less"]);
+        let positions: Vec<(usize, usize, usize, usize)> = map
+            .decode()
+            .iter()
+            .map(|m| {
+                (
+                    m.generated_line,
+                    m.generated_column,
+                    m.original_line,
+                    m.original_column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, 0, 2, 4),
+                (0, 2, 2, 4),
+                (0, 11, 2, 4),
+                (0, 12, 2, 4),
+                (1, 2, 5, 0),
+                (1, 7, 5, 0),
+                (1, 8, 5, 0),
+            ]
+        );
+
+        // decoding a malformed map stops at the first invalid segment
+        let mut map = generator::SourceMap::new(None, vec![String::from("a.less")], None, &[]);
+        for mappings in ["AAAA,g", "AAAA,gggggggggggggggB"] {
+            map.mappings = String::from(mappings);
+            let positions: Vec<(usize, usize)> = map
+                .decode()
+                .iter()
+                .map(|m| (m.generated_line, m.generated_column))
+                .collect();
+            assert_eq!(positions, vec![(0, 0)]);
+        }
+    }
+
+    #[test]
+    fn write_minified() {
+        let source = r###"/*! license */
+/* note */
+@media screen and ( max-width : 100px ) {
+    a > b ,  .c  ~  d {
+        color : #FFFFFF ;
+        margin: 0px 0.0em calc(0px + 1em) 10px; // inline
+        display: -webkit-box;
+        display: flex;
+        color: #FFFFFF;
+        border: 1px solid #AABBCD !important;
+        border: none;
+    }
+    .empty { /* nothing */ }
+}
+@font-face {}
+"###;
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let nodes = parser::Parser::new(&tokens).parse().nodes;
+        assert_eq!(
+            minifier::minify(&nodes, &minifier::MinifyOptions::default()),
+            "/*! license*/@media screen and (max-width:100px){a>b,.c~d{margin:0 0 calc(0px + 1em) 10px;display:-webkit-box;display:flex;color:#FFF;border:1px solid #AABBCD!important;border:none}}@font-face{}"
+        );
+        assert_eq!(
+            minifier::minify(&nodes, &minifier::MinifyOptions { aggressive: true }),
+            "@media screen and (max-width:100px){a>b,.c~d{margin:0 0 calc(0px + 1em) 10px;display:flex;color:#FFF;border:1px solid #AABBCD!important}}"
+        );
+    }
+
+    #[test]
+    fn write_formatted() {
+        let source = "/* header */\n@import  'a.css' ;\n.a,.b>.c{color:#FFF;background : url( 'x.png' )   no-repeat ! important;// note\n  content:'it\"s';font-family:'A' , 'B'}\n\n\n@media screen and (max-width:100px){.d{margin:0 /* keep */ auto}}";
+        let options = formatter::FormatOptions::default();
+        let output = formatter::format(source, &options).unwrap();
+        assert_eq!(
+            output,
+            r###"/* header */
+@import "a.css";
+
+.a,
+.b > .c {
+    color: #fff;
+    background: url("x.png") no-repeat !important; // note
+    content: 'it"s';
+    font-family: "A", "B";
+}
+
+@media screen and (max-width: 100px) {
+    .d {
+        margin: 0 /* keep */ auto;
+    }
+}
+"###
+        );
+        assert_eq!(formatter::format(&output, &options).unwrap(), output);
+
+        let options = formatter::FormatOptions {
+            use_tabs: true,
+            selector_per_line: false,
+            blank_line_between_rules: false,
+            quote: formatter::Quote::Single,
+            ..formatter::FormatOptions::default()
+        };
+        let output = formatter::format(source, &options).unwrap();
+        assert!(output.contains("@import 'a.css';\n.a, .b > .c {\n\tcolor: #fff;"));
+        assert!(output.contains("\tfont-family: 'A', 'B';\n}\n\n@media"));
+        assert_eq!(formatter::format(&output, &options).unwrap(), output);
+
+        let source = include_str!("../../test/test.less");
+        let output = formatter::format(source, &options).unwrap();
+        assert_eq!(formatter::format(&output, &options).unwrap(), output);
+        assert!(formatter::format("a { color red }", &options).is_err());
+
+        let source = ".a {\n  grid-template-areas:\n    \"a b\" // header\n    \"c d\";\n  margin: 0 // end\n  ;\n}";
+        let options = formatter::FormatOptions::default();
+        let output = formatter::format(source, &options).unwrap();
+        assert_eq!(
+            output,
+            ".a {\n    grid-template-areas: \"a b\" // header\n        \"c d\";\n    margin: 0 // end\n        ;\n}\n"
+        );
+        assert_eq!(formatter::format(&output, &options).unwrap(), output);
+    }
+
+    #[test]
+    fn read_selectors() {
+        let source = "svg|a > b.c#d, *|* + [lang|=\"en\" i] ~ |e || f, & -item, &-item:not(.x, .y)::before, li:nth-child(2n + 1 of .a):has(> img):hover, .mixin() {}";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        let selectors = match &result.nodes[0] {
+            NodeType::Rule { selectors, .. } => selectors.to_owned(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        // a less mixin is not a selector
+        assert_eq!(selectors, None);
+
+        let source = source.replace(", .mixin()", "");
+        let tokens = lexer::Lexer::new().lex_from_source(&source);
+        let result = parser::Parser::new(&tokens).parse();
+        let list = match &result.nodes[0] {
+            NodeType::Rule { selectors, .. } => selectors.to_owned().unwrap(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(list.len(), 5);
+        let combinators = |index: usize| -> Vec<Option<Combinator>> {
+            list[index]
+                .parts
+                .iter()
+                .map(|part| part.combinator.to_owned())
+                .collect()
+        };
+        let compound = |index: usize, part: usize| list[index].parts[part].compound.to_owned();
+
+        assert_eq!(combinators(0), vec![None, Some(Combinator::Child)]);
+        assert_eq!(
+            compound(0, 0),
+            vec![SimpleSelector::Type {
+                namespace: Some(String::from("svg")),
+                name: String::from("a"),
+            }]
+        );
+        assert_eq!(
+            compound(0, 1),
+            vec![
+                SimpleSelector::Type {
+                    namespace: None,
+                    name: String::from("b"),
+                },
+                SimpleSelector::Class(String::from("c")),
+                SimpleSelector::Id(String::from("d")),
+            ]
+        );
+
+        assert_eq!(
+            combinators(1),
+            vec![
+                None,
+                Some(Combinator::NextSibling),
+                Some(Combinator::SubsequentSibling),
+                Some(Combinator::Column),
+            ]
+        );
+        assert_eq!(
+            compound(1, 0),
+            vec![SimpleSelector::Universal {
+                namespace: Some(String::from("*")),
+            }]
+        );
+        assert_eq!(
+            compound(1, 1),
+            vec![SimpleSelector::Attribute {
+                namespace: None,
+                name: String::from("lang"),
+                operator: Some(AttributeOperator::DashMatch),
+                value: Some(String::from("en")),
+                modifier: Some('i'),
+            }]
+        );
+        assert_eq!(
+            compound(1, 2),
+            vec![SimpleSelector::Type {
+                namespace: Some(String::new()),
+                name: String::from("e"),
+            }]
+        );
+
+        assert_eq!(combinators(2), vec![None, Some(Combinator::Descendant)]);
+        assert_eq!(
+            compound(2, 0),
+            vec![SimpleSelector::Nesting { suffix: None }]
+        );
+
+        match &compound(3, 0)[..] {
+            [SimpleSelector::Nesting { suffix }, SimpleSelector::PseudoClass {
+                name,
+                argument: Some(PseudoArgument::Selectors(not)),
+            }, SimpleSelector::PseudoElement { name: element, .. }] => {
+                assert_eq!(suffix, &Some(String::from("-item")));
+                assert_eq!(name, "not");
+                assert_eq!(not.len(), 2);
+                assert_eq!(element, "before");
+            }
+            compound => panic!("unexpected compound {:?}", compound),
+        }
+
+        match &compound(4, 0)[..] {
+            [_, SimpleSelector::PseudoClass {
+                argument: Some(PseudoArgument::Nth { a, b, of: Some(of) }),
+                ..
+            }, SimpleSelector::PseudoClass {
+                name,
+                argument: Some(PseudoArgument::Selectors(has)),
+            }, SimpleSelector::PseudoClass { argument: None, .. }] => {
+                assert_eq!((*a, *b), (2, 1));
+                assert_eq!(
+                    of[0].parts[0].compound,
+                    vec![SimpleSelector::Class(String::from("a"))]
+                );
+                assert_eq!(name, "has");
+                assert_eq!(has[0].parts[0].combinator, Some(Combinator::Child));
+            }
+            compound => panic!("unexpected compound {:?}", compound),
+        }
+
+        let nth = |source: &str| -> Option<(i32, i32)> {
+            let tokens = lexer::Lexer::new().lex_from_source(&format!(":nth-child({})", source));
+            match parser::parse_selector_list(&tokens)?[0].parts[0].compound[0].to_owned() {
+                SimpleSelector::PseudoClass {
+                    argument: Some(PseudoArgument::Nth { a, b, .. }),
+                    ..
+                } => Some((a, b)),
+                _ => None,
+            }
+        };
+        assert_eq!(nth("odd"), Some((2, 1)));
+        assert_eq!(nth("even"), Some((2, 0)));
+        assert_eq!(nth("-n+3"), Some((-1, 3)));
+        assert_eq!(nth("n"), Some((1, 0)));
+        assert_eq!(nth("+5"), Some((0, 5)));
+        assert_eq!(nth("3n - 2"), Some((3, -2)));
+        assert_eq!(nth("3n 2"), None);
+    }
+
+    #[test]
+    fn read_values() {
+        let source = "a { width: calc(100% - 10px) !important; color: var(--white, #FFF); background: url(a.png), url(\"b.png\") no-repeat; font: 12px/1.5 /* note */ \"Helvetica\", sans-serif; grid-area: [a] 1 }";
+
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        let decls: Vec<(String, Vec<ComponentValue>)> = match &result.nodes[0] {
+            NodeType::Rule { nodes, .. } => nodes
+                .iter()
+                .map(|node| match node {
+                    NodeType::Decl { value, values, .. } => (value.to_owned(), values.to_owned()),
+                    node => panic!("unexpected node {:?}", node),
+                })
+                .collect(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        let ident = |name: &str| ComponentValue::Ident(String::from(name));
+
+        // the raw string is kept
+        assert_eq!(decls[0].0, "calc(100% - 10px)");
+        match &decls[0].1[..] {
+            [ComponentValue::Function { name, arguments }] => {
+                assert_eq!(name, "calc");
+                match &arguments[..] {
+                    [ComponentValue::Percentage(percentage), ComponentValue::Whitespace, ComponentValue::Delim('-'), ComponentValue::Whitespace, ComponentValue::Dimension { number, unit }] =>
+                    {
+                        assert_eq!(percentage.value, 100.0);
+                        assert_eq!((number.value, unit.as_str()), (10.0, "px"));
+                    }
+                    arguments => panic!("unexpected arguments {:?}", arguments),
+                }
+            }
+            values => panic!("unexpected values {:?}", values),
+        }
+
+        assert_eq!(
+            decls[1].1,
+            vec![ComponentValue::Function {
+                name: String::from("var"),
+                arguments: vec![
+                    ident("--white"),
+                    ComponentValue::Comma,
+                    ComponentValue::Color(String::from("FFF")),
+                ],
+            }]
+        );
+
+        assert_eq!(
+            decls[2].1,
+            vec![
+                ComponentValue::Url(String::from("a.png")),
+                ComponentValue::Comma,
+                ComponentValue::Function {
+                    name: String::from("url"),
+                    arguments: vec![ComponentValue::String(String::from("b.png"))],
+                },
+                ComponentValue::Whitespace,
+                ident("no-repeat"),
+            ]
+        );
+
+        // comments are dropped, no whitespace around separators
+        match &decls[3].1[..] {
+            [ComponentValue::Dimension { .. }, ComponentValue::Slash, ComponentValue::Number(_), ComponentValue::Whitespace, ComponentValue::String(family), ComponentValue::Comma, ComponentValue::Ident(_)] =>
+            {
+                assert_eq!(family, "Helvetica")
+            }
+            values => panic!("unexpected values {:?}", values),
+        }
+
+        match &decls[4].1[..] {
+            [ComponentValue::Block { open: '[', values }, ComponentValue::Whitespace, ComponentValue::Number(_)] =>
+            {
+                assert_eq!(values, &vec![ident("a")])
+            }
+            values => panic!("unexpected values {:?}", values),
+        }
+    }
+
+    #[test]
+    fn read_media_queries() {
+        let prelude = |source: &str| -> Option<AtrulePrelude> {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            match &parser::Parser::new(&tokens).parse().nodes[0] {
+                NodeType::Atrule { prelude, .. } => prelude.to_owned(),
+                node => panic!("unexpected node {:?}", node),
+            }
+        };
+        let media = |source: &str| match prelude(&format!("@media {} {{}}", source)) {
+            Some(AtrulePrelude::Media(list)) => Some(list),
+            None => None,
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        };
+        let px = |value: f64| MediaValue::Dimension {
+            value,
+            unit: String::from("px"),
+        };
+
+        let list = media("only Screen and (max-width: 1000px), print").unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].modifier, Some(MediaModifier::Only));
+        assert_eq!(list[0].media_type.as_deref(), Some("screen"));
+        assert_eq!(
+            list[0].condition,
+            Some(MediaCondition::Feature(MediaFeature::Plain {
+                name: String::from("max-width"),
+                value: px(1000.0),
+            }))
+        );
+        assert_eq!(list[1].media_type.as_deref(), Some("print"));
+        assert_eq!(list[1].condition, None);
+
+        let list = media(
+            "not (color), (400px <= width < 700px), (600px < width) and (aspect-ratio: 16/9)",
+        )
+        .unwrap();
+        assert_eq!(list[0].modifier, None);
+        match &list[0].condition {
+            Some(MediaCondition::Not(condition)) => assert_eq!(
+                **condition,
+                MediaCondition::Feature(MediaFeature::Boolean {
+                    name: String::from("color")
+                })
+            ),
+            condition => panic!("unexpected condition {:?}", condition),
+        }
+        assert_eq!(
+            list[1].condition,
+            Some(MediaCondition::Feature(MediaFeature::Interval {
+                name: String::from("width"),
+                start: px(400.0),
+                start_operator: RangeOperator::Le,
+                end_operator: RangeOperator::Lt,
+                end: px(700.0),
+            }))
+        );
+        // value first is turned to name first
+        assert_eq!(
+            list[2].condition,
+            Some(MediaCondition::And(vec![
+                MediaCondition::Feature(MediaFeature::Range {
+                    name: String::from("width"),
+                    operator: RangeOperator::Gt,
+                    value: px(600.0),
+                }),
+                MediaCondition::Feature(MediaFeature::Plain {
+                    name: String::from("aspect-ratio"),
+                    value: MediaValue::Ratio(16.0, 9.0),
+                }),
+            ]))
+        );
+        assert_eq!(
+            media("((color) or (hover)) and foo(bar)").unwrap()[0].condition,
+            Some(MediaCondition::And(vec![
+                MediaCondition::Or(vec![
+                    MediaCondition::Feature(MediaFeature::Boolean {
+                        name: String::from("color")
+                    }),
+                    MediaCondition::Feature(MediaFeature::Boolean {
+                        name: String::from("hover")
+                    }),
+                ]),
+                MediaCondition::GeneralEnclosed(String::from("foo(bar)")),
+            ]))
+        );
+        assert_eq!(media("").unwrap(), vec![]);
+        // "and" and "or" can't be mixed, "or" not after a media type
+        assert_eq!(media("(color) and (hover) or (grid)"), None);
+        assert_eq!(media("screen and (color) or (hover)"), None);
+        assert_eq!(media("not (color) and (hover)"), None);
+        // less variable of a media query
+        assert_eq!(
+            media("@phone and (color)").unwrap()[0].condition,
+            Some(MediaCondition::And(vec![
+                MediaCondition::Variable(String::from("phone")),
+                MediaCondition::Feature(MediaFeature::Boolean {
+                    name: String::from("color")
+                }),
+            ]))
+        );
+
+        match prelude("@import url(\"a.css\") layer(base) supports(display: grid) screen;") {
+            Some(AtrulePrelude::Import {
+                url,
+                layer,
+                supports,
+                media,
+            }) => {
+                assert_eq!(url, "a.css");
+                assert_eq!(layer.as_deref(), Some("base"));
+                assert_eq!(
+                    supports,
+                    Some(SupportsCondition::Declaration {
+                        prop: String::from("display"),
+                        value: String::from("grid"),
+                    })
+                );
+                assert_eq!(media[0].media_type.as_deref(), Some("screen"));
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        match prelude("@import 'b.css';") {
+            Some(AtrulePrelude::Import { url, media, .. }) => {
+                assert_eq!(url, "b.css");
+                assert!(media.is_empty());
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        match prelude("@custom-media --narrow (max-width: 30em);") {
+            Some(AtrulePrelude::CustomMedia { name, media }) => {
+                assert_eq!(name, "--narrow");
+                assert_eq!(media[0].media_type, None);
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        match prelude("@custom-media --never false;") {
+            Some(AtrulePrelude::CustomMedia { media, .. }) => {
+                assert_eq!(media[0].modifier, Some(MediaModifier::Not));
+                assert_eq!(media[0].media_type.as_deref(), Some("all"));
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        assert_eq!(prelude("@foo bar {}"), None);
+    }
+
+    #[test]
+    fn match_media_queries() {
+        let matches = |source: &str, environment: &Environment| -> bool {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            let tokens: Vec<&Token> = tokens.iter().collect();
+            parser::matches_media(
+                &parser::parse_media_query_list(&tokens).unwrap(),
+                environment,
+            )
+        };
+        let desktop = Environment::default();
+        let phone = Environment {
+            width: 375.0,
+            height: 812.0,
+            resolution: 3.0,
+            hover: String::from("none"),
+            pointer: String::from("coarse"),
+            prefers_color_scheme: String::from("dark"),
+            ..Environment::default()
+        };
+
+        assert!(matches("", &phone));
+        assert!(matches("screen", &phone));
+        assert!(!matches("print", &phone));
+        assert!(matches("not print", &phone));
+        assert!(matches("only screen and (max-width: 600px)", &phone));
+        assert!(!matches("only screen and (max-width: 600px)", &desktop));
+        assert!(matches("(min-width: 40em)", &desktop));
+        assert!(matches("(400px <= width < 1200px)", &desktop));
+        assert!(!matches("(400px <= width < 1200px)", &phone));
+        assert!(matches("(600px > width)", &phone));
+        assert!(matches("(orientation: portrait)", &phone));
+        assert!(matches("(orientation: landscape)", &desktop));
+        assert!(matches("(min-aspect-ratio: 4/3)", &desktop));
+        assert!(matches(
+            "(min-resolution: 2dppx) and (prefers-color-scheme: dark)",
+            &phone
+        ));
+        assert!(matches("(min-resolution: 192dpi)", &phone));
+        assert!(!matches("(hover) or (pointer: fine)", &phone));
+        assert!(matches("(hover) or (pointer: fine)", &desktop));
+        assert!(matches("not (prefers-reduced-motion: reduce)", &desktop));
+        assert!(matches("print, (max-width: 600px)", &phone));
+        // unknown features are false, also after "not"
+        assert!(!matches("(unknown-feature)", &desktop));
+        assert!(!matches("not screen and (unknown-feature)", &desktop));
+        assert!(!matches("not (width: 10deg)", &desktop));
+        assert!(matches("(unknown-feature) or (color)", &desktop));
+    }
+
+    #[test]
+    fn read_supports() {
+        let condition = |source: &str| -> Option<SupportsCondition> {
+            let tokens = lexer::Lexer::new().lex_from_source(&format!("@supports {} {{}}", source));
+            match &parser::Parser::new(&tokens).parse().nodes[0] {
+                NodeType::Atrule {
+                    prelude: Some(AtrulePrelude::Supports(condition)),
+                    ..
+                } => Some(condition.to_owned()),
+                NodeType::Atrule { prelude: None, .. } => None,
+                node => panic!("unexpected node {:?}", node),
+            }
+        };
+        let declaration = |prop: &str, value: &str| SupportsCondition::Declaration {
+            prop: String::from(prop),
+            value: String::from(value),
+        };
+
+        assert_eq!(
+            condition("(display: grid) and (not (display: inline-grid))"),
+            Some(SupportsCondition::And(vec![
+                declaration("display", "grid"),
+                SupportsCondition::Not(Box::new(declaration("display", "inline-grid"))),
+            ]))
+        );
+        assert_eq!(
+            condition("((transform: none) or (-webkit-transform: none)) and selector(a:has(> b))"),
+            Some(SupportsCondition::And(vec![
+                SupportsCondition::Or(vec![
+                    declaration("transform", "none"),
+                    declaration("-webkit-transform", "none"),
+                ]),
+                SupportsCondition::Selector(String::from("a:has(> b)")),
+            ]))
+        );
+        assert_eq!(
+            condition("font-tech(color-COLRv1) or font-format(woff2) or foo(bar)"),
+            Some(SupportsCondition::Or(vec![
+                SupportsCondition::FontTech(String::from("color-COLRv1")),
+                SupportsCondition::FontFormat(String::from("woff2")),
+                SupportsCondition::GeneralEnclosed(String::from("foo(bar)")),
+            ]))
+        );
+        assert_eq!(condition("display: grid"), None);
+        assert_eq!(condition("(a: b) and (c: d) or (e: f)"), None);
+        assert_eq!(condition("not (a: b) and (c: d)"), None);
+
+        let mut features = SupportedFeatures {
+            pseudos: Some(vec![String::from("hover"), String::from("not")]),
+            font_formats: vec![String::from("woff2")],
+            ..SupportedFeatures::default()
+        };
+        features.declarations.insert(
+            String::from("display"),
+            vec![String::from("grid"), String::from("flex")],
+        );
+        features.declarations.insert(String::from("gap"), vec![]);
+        let matches = |source: &str| condition(source).unwrap().matches(&features);
+
+        assert!(matches("(display: grid) and (not (display: inline-grid))"));
+        assert!(matches("(DISPLAY: Flex)"));
+        assert!(matches("(gap: 1px 2px)"));
+        assert!(!matches("(float: left)"));
+        assert!(matches("(float: left) or (display: grid)"));
+        assert!(matches("selector(a:not(:hover))"));
+        assert!(!matches("selector(a:has(> b))"));
+        assert!(!matches("selector(a[)"));
+        assert!(matches("font-format(WOFF2)"));
+        assert!(!matches("font-tech(color-COLRv1)"));
+        assert!(!matches("foo(bar)"));
+        assert!(matches("not foo(bar)"));
+    }
+
+    #[test]
+    fn read_atrules() {
+        let parse = |source: &str| {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            parser::Parser::new(&tokens).parse()
+        };
+        let prelude = |source: &str| -> Option<AtrulePrelude> {
+            let result = parse(source);
+            assert_eq!(result.errors, vec![], "{}", source);
+            match &result.nodes[0] {
+                NodeType::Atrule { prelude, .. } => prelude.to_owned(),
+                node => panic!("unexpected node {:?}", node),
+            }
+        };
+        let errors = |source: &str| -> Vec<ParseErrorKind> {
+            parse(source)
+                .errors
+                .into_iter()
+                .map(|error| error.kind)
+                .collect()
+        };
+
+        assert_eq!(
+            prelude("@charset \"utf-8\";"),
+            Some(AtrulePrelude::Charset(String::from("utf-8")))
+        );
+        assert_eq!(
+            prelude("@namespace svg url(http://www.w3.org/2000/svg);"),
+            Some(AtrulePrelude::Namespace {
+                prefix: Some(String::from("svg")),
+                url: String::from("http://www.w3.org/2000/svg"),
+            })
+        );
+        assert_eq!(
+            prelude("@font-face { font-family: Foo; src: url(foo.woff2) format(\"woff2\"); font-display: swap }"),
+            Some(AtrulePrelude::FontFace)
+        );
+        assert_eq!(
+            prelude("@page toc, index:blank, :first { margin: 1in; @top-left { content: \"x\" } }"),
+            Some(AtrulePrelude::Page(vec![
+                PageSelector {
+                    name: Some(String::from("toc")),
+                    pseudos: vec![],
+                },
+                PageSelector {
+                    name: Some(String::from("index")),
+                    pseudos: vec![String::from("blank")],
+                },
+                PageSelector {
+                    name: None,
+                    pseudos: vec![String::from("first")],
+                },
+            ]))
+        );
+        match &parse("@page { @top-left { content: \"x\" } }").nodes[0] {
+            NodeType::Atrule {
+                nodes: Some(nodes), ..
+            } => match &nodes[0] {
+                NodeType::Atrule { prelude, .. } => assert_eq!(
+                    prelude,
+                    &Some(AtrulePrelude::PageMargin(String::from("top-left")))
+                ),
+                node => panic!("unexpected node {:?}", node),
+            },
+            node => panic!("unexpected node {:?}", node),
+        }
+        assert_eq!(
+            prelude("@layer reset, base.theme;"),
+            Some(AtrulePrelude::Layer(vec![
+                String::from("reset"),
+                String::from("base.theme")
+            ]))
+        );
+        assert_eq!(
+            prelude("@layer { a {} }"),
+            Some(AtrulePrelude::Layer(vec![]))
+        );
+        match prelude("@container sidebar (min-width: 400px) { a {} }") {
+            Some(AtrulePrelude::Container {
+                name,
+                condition: MediaCondition::Feature(MediaFeature::Plain { name: feature, .. }),
+            }) => {
+                assert_eq!(name.as_deref(), Some("sidebar"));
+                assert_eq!(feature, "min-width");
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        match prelude("@container style(--responsive: true) {}") {
+            Some(AtrulePrelude::Container {
+                name: None,
+                condition: MediaCondition::GeneralEnclosed(query),
+            }) => assert_eq!(query, "style(--responsive: true)"),
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        assert_eq!(
+            prelude(
+                "@property --angle { syntax: \"<angle>\"; inherits: false; initial-value: 0deg }"
+            ),
+            Some(AtrulePrelude::Property(String::from("--angle")))
+        );
+        assert_eq!(
+            prelude("@property --any { syntax: \"*\"; inherits: true }"),
+            Some(AtrulePrelude::Property(String::from("--any")))
+        );
+        assert_eq!(
+            prelude("@counter-style thumbs { system: cyclic; symbols: \"👍\"; suffix: \" \" }"),
+            Some(AtrulePrelude::CounterStyle(String::from("thumbs")))
+        );
+        assert_eq!(
+            prelude("@font-feature-values Font One, \"Font Two\" { font-display: swap; @styleset { nice-style: 12; } }"),
+            Some(AtrulePrelude::FontFeatureValues(vec![
+                String::from("Font One"),
+                String::from("Font Two")
+            ]))
+        );
+        match prelude("@scope (.card) to (.content) { img {} }") {
+            Some(AtrulePrelude::Scope {
+                root: Some(root),
+                limit: Some(limit),
+            }) => {
+                assert_eq!(
+                    root[0].parts[0].compound,
+                    vec![SimpleSelector::Class(String::from("card"))]
+                );
+                assert_eq!(
+                    limit[0].parts[0].compound,
+                    vec![SimpleSelector::Class(String::from("content"))]
+                );
+            }
+            prelude => panic!("unexpected prelude {:?}", prelude),
+        }
+        assert_eq!(
+            prelude("@scope { a {} }"),
+            Some(AtrulePrelude::Scope {
+                root: None,
+                limit: None
+            })
+        );
+        assert_eq!(
+            prelude("@starting-style { a { opacity: 0 } }"),
+            Some(AtrulePrelude::StartingStyle)
+        );
+
+        assert_eq!(
+            errors("@charset utf-8;"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(
+            errors("@charset \"utf-8\" {}"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(
+            errors("@font-face foo {}"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(
+            errors("@layer a, b {}"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(errors("@layer a b;"), vec![ParseErrorKind::InvalidAtrule]);
+        assert_eq!(errors("@layer a . b;"), vec![ParseErrorKind::InvalidAtrule]);
+        assert_eq!(errors("@property angle {}").len(), 1);
+        assert_eq!(
+            errors("@counter-style none {}"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(
+            errors("@scope (.a) to {}"),
+            vec![ParseErrorKind::InvalidAtrule]
+        );
+        assert_eq!(
+            errors("@starting-style;"),
+            vec![ParseErrorKind::EmptyAtruleParams]
+        );
+        assert_eq!(
+            errors(
+                "@font-face { font-family: Foo; src: url(a.woff); color: red; font-display: fast }"
+            ),
+            vec![
+                ParseErrorKind::InvalidDescriptor,
+                ParseErrorKind::InvalidDescriptor
+            ]
+        );
+        assert_eq!(
+            errors("@font-face { font-family: Foo }"),
+            vec![ParseErrorKind::MissingDescriptor]
+        );
+        assert_eq!(
+            errors("@property --a { syntax: <length>; inherits: maybe }"),
+            vec![
+                ParseErrorKind::InvalidDescriptor,
+                ParseErrorKind::InvalidDescriptor,
+                ParseErrorKind::MissingDescriptor
+            ]
+        );
+        assert_eq!(
+            errors("@counter-style a { system: cyclic; colour: red }"),
+            vec![ParseErrorKind::InvalidDescriptor]
+        );
+        assert_eq!(
+            errors("@font-feature-values Foo { @swash { fancy: 1 two } }"),
+            vec![ParseErrorKind::InvalidDescriptor]
+        );
+        // unknown and less at-rules are not checked
+        assert_eq!(errors("@foo bar { color: red }"), vec![]);
+        assert_eq!(errors("@media @phone { a {} }"), vec![]);
+        // nor are less variables and vendor prefixes in descriptors
+        assert_eq!(
+            errors("@font-face { font-family: Foo; src: url(a.woff); font-display: @display; -moz-font-feature-settings: \"liga\"; @{prop}: 1 }"),
+            vec![]
+        );
+        // a strict parse doesn't fail on descriptors
+        let tokens =
+            lexer::Lexer::new().lex_from_source("@font-face { font-family: Foo; colour: red }");
+        assert!(parser::Parser::new(&tokens).parse_strict().is_ok());
+        let tokens = lexer::Lexer::new().lex_from_source("@font-face foo {}");
+        assert!(parser::Parser::new(&tokens).parse_strict().is_err());
+    }
+
+    #[test]
+    fn read_keyframes() {
+        let source = "@keyframes spin { from { rotate: 0deg } 50%, 75.5% { rotate: 90deg } TO { rotate: 360deg } }\n@-webkit-keyframes reveal { entry 10%, exit 100% { opacity: 1 } }";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        let keyframes = |node: &NodeType| -> Vec<(String, Vec<f32>, Vec<Option<String>>)> {
+            match node {
+                NodeType::Atrule {
+                    nodes: Some(nodes), ..
+                } => nodes
+                    .iter()
+                    .map(|node| match node {
+                        NodeType::Keyframe {
+                            selector,
+                            offsets,
+                            ranges,
+                            ..
+                        } => (selector.to_owned(), offsets.to_owned(), ranges.to_owned()),
+                        node => panic!("unexpected node {:?}", node),
+                    })
+                    .collect(),
+                node => panic!("unexpected node {:?}", node),
+            }
+        };
+        assert_eq!(
+            keyframes(&result.nodes[0]),
+            vec![
+                (String::from("from"), vec![0.0], vec![None]),
+                (
+                    String::from("50%, 75.5%"),
+                    vec![0.5, 0.755],
+                    vec![None, None]
+                ),
+                (String::from("TO"), vec![1.0], vec![None]),
+            ]
+        );
+        assert_eq!(
+            keyframes(&result.nodes[1]),
+            vec![(
+                String::from("entry 10%, exit 100%"),
+                vec![0.1, 1.0],
+                vec![Some(String::from("entry")), Some(String::from("exit"))]
+            )]
+        );
+        let options = generator::Options::default();
+        let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+        assert_eq!(output, source);
+        assert_eq!(
+            minifier::minify(&result.nodes, &minifier::MinifyOptions::default()),
+            "@keyframes spin{from{rotate:0deg}50%,75.5%{rotate:90deg}TO{rotate:360deg}}@-webkit-keyframes reveal{entry 10%,exit 100%{opacity:1}}"
+        );
+
+        let errors = |source: &str| -> Vec<ParseErrorKind> {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            parser::Parser::new(&tokens)
+                .parse()
+                .errors
+                .into_iter()
+                .map(|error| error.kind)
+                .collect()
+        };
+        assert_eq!(
+            errors("@keyframes a { 0%, 150% {} .b {} from, to {} }"),
+            vec![
+                ParseErrorKind::InvalidKeyframeSelector,
+                ParseErrorKind::InvalidKeyframeSelector
+            ]
+        );
+        assert_eq!(
+            errors("@keyframes a { from { top: 0 !important; left: 0 } }"),
+            vec![ParseErrorKind::ImportantInKeyframe]
+        );
+        assert_eq!(
+            errors("@keyframes a { middle 10% {} }"),
+            vec![ParseErrorKind::InvalidKeyframeSelector]
+        );
+        // "!important" is fine in rules outside keyframes
+        assert_eq!(errors("@media print { a { top: 0 !important } }"), vec![]);
+    }
+
+    #[test]
+    fn read_mixins() {
+        let source = ".bordered(@width: 2px; @style...) when (@width > 1px) {\n  border: @width @style;\n}\n.m(dark, @color) { color: @color }\n#main {\n  .bordered(4px);\n  #ns > .mixin();\n  #ns.mixin(@color: red, 1px) ! important;\n  .m;\n  .a { top: 0 }\n  .b\n}";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        match &result.nodes[0] {
+            NodeType::MixinDefinition {
+                name,
+                params,
+                defaults,
+                rest,
+                guard,
+                selector,
+                nodes,
+                ..
+            } => {
+                assert_eq!(name, ".bordered");
+                assert_eq!(params, &vec![String::from("@width")]);
+                assert_eq!(defaults, &vec![Some(String::from("2px"))]);
+                assert_eq!(rest, &Some(String::from("@style")));
+                assert_eq!(
+                    guard,
+                    &Some(Guard::Compare {
+                        left: String::from("@width"),
+                        operator: RangeOperator::Gt,
+                        right: String::from("1px"),
+                    })
+                );
+                assert_eq!(
+                    selector,
+                    ".bordered(@width: 2px; @style...) when (@width > 1px)"
+                );
+                assert_eq!(nodes.len(), 1);
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        match &result.nodes[1] {
+            NodeType::MixinDefinition {
+                params,
+                defaults,
+                rest,
+                guard,
+                ..
+            } => {
+                assert_eq!(params, &vec![String::from("dark"), String::from("@color")]);
+                assert_eq!(defaults, &vec![None, None]);
+                assert_eq!(rest, &None);
+                assert_eq!(guard, &None);
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        let calls: Vec<(String, Vec<String>, Vec<MixinArgument>, bool)> = match &result.nodes[2] {
+            NodeType::Rule { nodes, .. } => nodes
+                .iter()
+                .filter_map(|node| match node {
+                    NodeType::MixinCall {
+                        name,
+                        namespace,
+                        args,
+                        important,
+                        ..
+                    } => Some((
+                        name.to_owned(),
+                        namespace.to_owned(),
+                        args.to_owned(),
+                        *important,
+                    )),
+                    _ => None,
+                })
+                .collect(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        let arg = |name: Option<&str>, value: &str| MixinArgument {
+            name: name.map(String::from),
+            value: value.to_owned(),
+        };
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    String::from(".bordered"),
+                    vec![],
+                    vec![arg(None, "4px")],
+                    false
+                ),
+                (
+                    String::from(".mixin"),
+                    vec![String::from("#ns")],
+                    vec![],
+                    false
+                ),
+                (
+                    String::from(".mixin"),
+                    vec![String::from("#ns")],
+                    vec![arg(Some("@color"), "red"), arg(None, "1px")],
+                    true
+                ),
+                (String::from(".m"), vec![], vec![], false),
+                (String::from(".b"), vec![], vec![], false),
+            ]
+        );
+        let options = generator::Options::default();
+        let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+        assert_eq!(output, source);
+        assert_eq!(
+            minifier::minify(&result.nodes[2..], &minifier::MinifyOptions::default()),
+            "#main{.bordered(4px);#ns>.mixin();#ns.mixin(@color: red,1px)!important;.m;.a{top:0}.b}"
+        );
+    }
+
+    #[test]
+    fn read_guards() {
+        let source = ".m(@a) when (iscolor(@a)) and (@a > 10), not (@b =< 2) { top: 0 }\n.m(@a) when (default()) { top: 1px }\n.box { & when (@mode = dark) { color: white } }\nbutton when ((@a) or (@debug)) { top: 2px }";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        let text = |s: &str| String::from(s);
+        let compare = |left: &str, operator: RangeOperator, right: &str| Guard::Compare {
+            left: text(left),
+            operator,
+            right: text(right),
+        };
+        let guards: Vec<Guard> = result
+            .nodes
+            .iter()
+            .map(|node| match node {
+                NodeType::MixinDefinition {
+                    guard: Some(guard), ..
+                }
+                | NodeType::Rule {
+                    guard: Some(guard), ..
+                } => guard.to_owned(),
+                NodeType::Rule { nodes, .. } => match &nodes[0] {
+                    NodeType::Rule {
+                        guard: Some(guard),
+                        selectors,
+                        ..
+                    } => {
+                        assert!(selectors.is_some());
+                        guard.to_owned()
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                },
+                node => panic!("unexpected node {:?}", node),
+            })
+            .collect();
+        assert_eq!(
+            guards,
+            vec![
+                Guard::Or(vec![
+                    Guard::And(vec![
+                        Guard::Value(text("iscolor(@a)")),
+                        compare("@a", RangeOperator::Gt, "10"),
+                    ]),
+                    Guard::Not(Box::new(compare("@b", RangeOperator::Le, "2"))),
+                ]),
+                Guard::Value(text("default()")),
+                compare("@mode", RangeOperator::Eq, "dark"),
+                Guard::Or(vec![Guard::Value(text("@a")), Guard::Value(text("@debug"))]),
+            ]
+        );
+        match &result.nodes[3] {
+            NodeType::Rule {
+                selector,
+                selectors,
+                ..
+            } => {
+                assert_eq!(selector, "button when ((@a) or (@debug))");
+                assert!(selectors.is_some());
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        let options = generator::Options::default();
+        let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+        assert_eq!(output, source);
+
+        // numbers and "true" of a few variables, "default()" is false
+        struct Variables;
+        impl GuardEvaluator for Variables {
+            fn truthy(&mut self, operand: &str) -> bool {
+                matches!(operand, "@debug" | "iscolor(@a)")
+            }
+            fn compare(&mut self, left: &str, right: &str) -> Option<std::cmp::Ordering> {
+                let value = |operand: &str| match operand {
+                    "@a" => Some(20.0),
+                    "@b" => Some(3.0),
+                    operand => operand.parse::<f64>().ok(),
+                };
+                value(left)?.partial_cmp(&value(right)?)
+            }
+        }
+        let results: Vec<bool> = guards
+            .iter()
+            .map(|guard| guard.evaluate(&mut Variables))
+            .collect();
+        assert_eq!(results, vec![true, false, false, true]);
+
+        let errors = |source: &str| -> Vec<ParseErrorKind> {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            parser::Parser::new(&tokens)
+                .parse()
+                .errors
+                .into_iter()
+                .map(|error| error.kind)
+                .collect()
+        };
+        assert_eq!(
+            errors(".m() when @a > 1 {}\na when (@a) and {}"),
+            vec![ParseErrorKind::InvalidGuard, ParseErrorKind::InvalidGuard]
+        );
+        // a class named "when" is not a guard
+        assert_eq!(errors("a .when {}"), vec![]);
+    }
+
+    #[test]
+    fn read_interpolation() {
+        let source = "@prefix: ui;\n.@{prefix}-button, #@{prefix}, h@{level} .btn-@{size} {\n  @{prop}-color: red;\n  background: url(\"@{base}/img.png\") @{fallback};\n  &-@{state} { top: 0 }\n}\n@media @phone { a { top: 0 } }";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        assert!(tokens.iter().any(|token| matches!(
+            token,
+            Token::Interpolation(name, _) if name.value == "prefix" && token.raw() == "@{prefix}"
+        )));
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        let interpolated =
+            |kind: Option<char>, name: &str, variables: &[&str]| SimpleSelector::Interpolated {
+                kind,
+                name: name.to_owned(),
+                variables: variables.iter().map(|s| s.to_string()).collect(),
+            };
+        match &result.nodes[1] {
+            NodeType::Rule {
+                selector,
+                selectors: Some(selectors),
+                nodes,
+                ..
+            } => {
+                assert_eq!(
+                    selector,
+                    ".@{prefix}-button, #@{prefix}, h@{level} .btn-@{size}"
+                );
+                let compounds: Vec<Vec<SimpleSelector>> = selectors
+                    .iter()
+                    .flat_map(|complex| complex.parts.iter())
+                    .map(|part| part.compound.to_owned())
+                    .collect();
+                assert_eq!(
+                    compounds,
+                    vec![
+                        vec![interpolated(Some('.'), "@{prefix}-button", &["prefix"])],
+                        vec![interpolated(Some('#'), "@{prefix}", &["prefix"])],
+                        vec![interpolated(None, "h@{level}", &["level"])],
+                        vec![interpolated(Some('.'), "btn-@{size}", &["size"])],
+                    ]
+                );
+                match &nodes[0] {
+                    NodeType::Decl { prop, value, .. } => {
+                        assert_eq!(prop, "@{prop}-color");
+                        assert_eq!(value, "red");
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+                match &nodes[1] {
+                    NodeType::Decl { values, .. } => assert_eq!(
+                        values,
+                        &vec![
+                            ComponentValue::Function {
+                                name: String::from("url"),
+                                arguments: vec![ComponentValue::String(String::from(
+                                    "@{base}/img.png"
+                                ))],
+                            },
+                            ComponentValue::Whitespace,
+                            ComponentValue::Interpolation(String::from("fallback")),
+                        ]
+                    ),
+                    node => panic!("unexpected node {:?}", node),
+                }
+                match &nodes[2] {
+                    NodeType::Rule {
+                        selectors: Some(selectors),
+                        ..
+                    } => assert_eq!(
+                        selectors[0].parts[0].compound,
+                        vec![SimpleSelector::Nesting {
+                            suffix: Some(String::from("-@{state}"))
+                        }]
+                    ),
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        match &result.nodes[2] {
+            NodeType::Atrule {
+                prelude: Some(AtrulePrelude::Media(queries)),
+                ..
+            } => assert_eq!(
+                queries[0].condition,
+                Some(MediaCondition::Variable(String::from("phone")))
+            ),
+            node => panic!("unexpected node {:?}", node),
+        }
+        let options = generator::Options::default();
+        let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+        assert_eq!(output, source);
+    }
+
+    #[test]
+    fn read_variables() {
+        let source = "@color: #999;\n@theme : dark;\n@bg: white ! important;\n@detached: {\n  background: red;\n}\n@config: { primary: blue; dark: { text: black } }\n@media print { a { top: 0 } }\n.box {\n  @detached();\n  color: @config[primary] @config[@mode][text];\n}";
+        let tokens = lexer::Lexer::new().lex_from_source(source);
+        let result = parser::Parser::new(&tokens).parse();
+        assert!(result.errors.is_empty());
+        let variables: Vec<(String, String, Option<bool>, Option<usize>)> = result
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                NodeType::VariableDecl {
+                    name,
+                    value,
+                    important,
+                    nodes,
+                    ..
+                } => Some((
+                    name.to_owned(),
+                    value.to_owned(),
+                    important.to_owned(),
+                    nodes.as_ref().map(|nodes| nodes.len()),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            variables,
+            vec![
+                (String::from("color"), String::from("#999"), None, None),
+                (String::from("bg"), String::from("white"), Some(true), None),
+                (String::from("detached"), String::new(), None, Some(1)),
+                (String::from("config"), String::new(), None, Some(2)),
+            ]
+        );
+        match &result.nodes[0] {
+            NodeType::VariableDecl { values, .. } => {
+                assert_eq!(values, &vec![ComponentValue::Color(String::from("999"))])
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        // "@theme :" is not a variable, and "@media" stays an at-rule
+        assert!(matches!(&result.nodes[1], NodeType::Atrule { name, .. } if name == "theme"));
+        assert!(matches!(&result.nodes[5], NodeType::Atrule { name, .. } if name == "media"));
+        match &result.nodes[6] {
+            NodeType::Rule { nodes, .. } => {
+                match &nodes[0] {
+                    NodeType::MixinCall {
+                        name,
+                        namespace,
+                        args,
+                        ..
+                    } => {
+                        assert_eq!(name, "@detached");
+                        assert!(namespace.is_empty() && args.is_empty());
+                    }
+                    node => panic!("unexpected node {:?}", node),
+                }
+                match &nodes[1] {
+                    NodeType::Decl { values, .. } => assert_eq!(
+                        values,
+                        &vec![
+                            ComponentValue::Lookup {
+                                variable: String::from("config"),
+                                keys: vec![String::from("primary")],
+                            },
+                            ComponentValue::Whitespace,
+                            ComponentValue::Lookup {
+                                variable: String::from("config"),
+                                keys: vec![String::from("@mode"), String::from("text")],
+                            },
+                        ]
+                    ),
+                    node => panic!("unexpected node {:?}", node),
+                }
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        let options = generator::Options::default();
+        let output = generator::Generator::new(&options).generate(&result.nodes, &result.raws);
+        assert_eq!(output, source);
+        assert_eq!(
+            minifier::minify(&result.nodes[2..4], &minifier::MinifyOptions::default()),
+            "@bg:white!important;@detached:{background:red}"
+        );
+    }
+
+    #[test]
+    fn compile_less() {
+        let compile = |source: &str| {
+            let tokens = lexer::Lexer::new().lex_from_source(source);
+            let result = parser::Parser::new(&tokens).parse();
+            let result = compiler::compile(&result.nodes);
+            for node in &result.nodes {
+                assert!(matches!(
+                    node,
+                    NodeType::Rule { .. } | NodeType::Atrule { .. } | NodeType::Decl { .. }
+                ));
+            }
+            let options = generator::Options::default();
+            let output =
+                generator::Generator::new(&options).generate(&result.nodes, &Raws::default());
+            let errors: Vec<compiler::CompileErrorKind> =
+                result.errors.into_iter().map(|error| error.kind).collect();
+            (output, errors)
+        };
+        assert_eq!(
+            compile(include_str!("../../test/test.less")),
+            (
+                String::from(
+                    r###"@import url();
+@media only screen and (max-width: 1000px) {
+    color: white;
+    font-size: 10px !important;
+}
+main {
+    color: #999;
+    -webkit-line-clamp: 3;
+}
+main * {
+    font-size: 3em;
+}
+main h3 {
+    font-size: large;
+    background: url("https://www.baidu.com");
+}
+main div + p {
+    list-style: "|";
+}
+main div + p :after {
+    content: "test";
+}
+main .test-1, main #test2 {
+    --theme-color: var(--white);
+}
+main [data-attr] {
+    background-color: white;
+    left: calc(100% - 10px);
+}
+main.img {
+    width: fit-content;
+}
+main div {
+    overflow: initial;
+}
+main #what {
+    right: 0;
+}
+.test-class1 {
+    color: white;
+}
+@keyframes anim {
+    from {
+        left: 0%;
+    }
+    to {
+        right: 100%;
+    }
+}"###
+                ),
+                vec![]
+            )
+        );
+        let source = r###"@width: @base * 2;
+@base: 10px;
+@color: #336699;
+@min768: ~"(min-width: 768px)";
+@prefix: btn;
+@config: { primary: blue; @dark: { primary: black; } }
+@detached: { background: red; };
+.bordered(@width: 2px; @style: solid) {
+  border: @width @style @color;
+}
+.theme(dark; @c) { color: darken(@c, 10%); }
+.theme(light; @c) { color: lighten(@c, 10%); }
+.size(@a) when (@a > 10) { width: @a; }
+.size(@a) when (default()) { width: auto; }
+.shadow(@rest...) { box-shadow: @arguments; }
+.loop(@i) when (@i > 0) {
+  .w-@{i} { width: (@i * 10px); }
+  .loop(@i - 1);
+}
+#ns { .m() { ns: yes; } }
+.plain { plain: 1; }
+.@{prefix} {
+  @width: 5px;
+  width: @width;
+  height: (@base / 2);
+  margin: -@base @base + 2;
+  font: 12px/1.5 sans;
+  .bordered(4px);
+  .theme(dark; @color);
+  .size(20);
+  .size(5) !important;
+  .shadow(1px, 2px);
+  #ns > .m();
+  .plain;
+  @detached();
+  p: @config[primary] @config[@dark][primary];
+  &-item { a: 1; }
+  & + & { b: 2; }
+  .x & { c: 3; }
+  @media @min768 {
+    d: 4;
+    @media (max-width: 1000px) { e: 5; }
+  }
+  & when (@base = 10px) { f: 6; }
+  & when (@base > 100px) { g: 7; }
+  content: "@{prefix}-x";
+}
+.loop(2);
+.outer { w: @width; }
+@media screen { .a { .b { c: d; } } }
+"###;
+        assert_eq!(
+            compile(source),
+            (
+                String::from(
+                    r###".plain {
+    plain: 1;
+}
+.btn {
+    width: 5px;
+    height: 5px;
+    margin: -10px 12px;
+    font: 12px/1.5 sans;
+    border: 4px solid #336699;
+    color: #264d73;
+    width: 20;
+    width: auto !important;
+    box-shadow: 1px 2px;
+    ns: yes;
+    plain: 1;
+    background: red;
+    p: blue black;
+    content: "btn-x";
+}
+.btn-item {
+    a: 1;
+}
+.btn + .btn {
+    b: 2;
+}
+.x .btn {
+    c: 3;
+}
+@media (min-width: 768px) {
+    .btn {
+        d: 4;
+    }
+}
+@media (min-width: 768px) and (max-width: 1000px) {
+    .btn {
+        e: 5;
+    }
+}
+.btn {
+    f: 6;
+}
+.w-2 {
+    width: 20px;
+}
+.w-1 {
+    width: 10px;
+}
+.outer {
+    w: 20px;
+}
+@media screen {
+    .a .b {
+        c: d;
+    }
+}"###
+                ),
+                vec![]
+            )
+        );
+        // an undefined variable or map is kept as it is, and reported
+        assert_eq!(
+            compile(".x { c: @config[primary]; d: @missing 1px; e: \"@{gone}\"; }"),
+            (
+                String::from(
+                    ".x {\n    c: @config[primary];\n    d: @missing 1px;\n    e: \"@{gone}\";\n}"
+                ),
+                vec![compiler::CompileErrorKind::UndefinedVariable; 3]
+            )
+        );
+        assert_eq!(
+            compile(".m(@a) { a: @a; }\n.x { .m(1; 2); .n; @d(); }"),
+            (
+                String::new(),
+                vec![
+                    compiler::CompileErrorKind::UnmatchedMixin,
+                    compiler::CompileErrorKind::UnmatchedMixin,
+                    compiler::CompileErrorKind::UndefinedVariable
+                ]
+            )
+        );
+        // a mixin calling itself is expanded once, not endlessly
+        assert_eq!(
+            compile(".a { .a; .a; }\n.b { .a; x: 1; }"),
+            (
+                String::from(".b {\n    x: 1;\n}"),
+                vec![compiler::CompileErrorKind::RecursiveMixin; 2]
+            )
+        );
+        // a guarded loop runs to its end
+        let (output, errors) = compile(
+            ".loop(@i) when (@i > 0) { .w-@{i} { width: @i; } .loop(@i - 1); }\n.loop(100);",
+        );
+        assert_eq!(output.matches("width").count(), 100);
+        assert!(output.ends_with(".w-1 {\n    width: 1;\n}"));
+        assert!(errors.is_empty());
+        // deeper ones are cut and reported
+        let (output, errors) = compile(
+            ".loop(@i) when (@i > 0) { .w-@{i} { width: @i; } .loop(@i - 1); }\n.loop(1000);",
+        );
+        assert_eq!(output.matches("width").count(), 150);
+        assert_eq!(errors, vec![compiler::CompileErrorKind::TooManyExpansions]);
     }
 }